@@ -0,0 +1,62 @@
+//! Times `World::step` on a large, busy world with 1, 2, 4, ... threads, up to the number
+//! of cores but at least 2, and checks every thread count ends up with the same grid.
+//!
+//! cargo bench --no-default-features --bench step
+
+use std::time::Instant;
+
+use fallingsand::{element::ElementRegistry, grid::Grid, replay::grid_hash, world::World};
+
+const WIDTH: i32 = 1024;
+const HEIGHT: i32 = 1024;
+const TICKS: u32 = 100;
+
+/// Sand, water and oil in stripes over the top three quarters, so it all falls and mixes.
+fn busy_world() -> World {
+    let elements = ElementRegistry::builtin();
+    let stripes = ["Sand", "Water", "Oil"].map(|name| elements.by_name(name).unwrap());
+    let mut world = World::new(Grid::with_size(WIDTH, HEIGHT), elements, 42);
+    for y in 0..HEIGHT * 3 / 4 {
+        for x in 0..WIDTH {
+            let element = stripes[(x / 8) as usize % stripes.len()];
+            let p = world.elements.spawn(element, 0, x, y);
+            world.grid.set(x, y, p);
+        }
+    }
+    world
+}
+
+fn main() {
+    // always compare at least two thread counts, even on a single core
+    let cores = std::thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .max(2);
+    let mut threads = 1;
+    let mut baseline = None;
+    println!("{}x{} grid, {} ticks", WIDTH, HEIGHT, TICKS);
+    while threads <= cores {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+        let mut world = busy_world();
+        let start = Instant::now();
+        pool.install(|| {
+            for _ in 0..TICKS {
+                world.step();
+            }
+        });
+        let elapsed = start.elapsed();
+        let hash = grid_hash(&world.grid);
+        let baseline = *baseline.get_or_insert((elapsed, hash));
+        println!(
+            "{:>3} threads: {:>8.2} ms/tick, {:.2}x, grid {:016x}{}",
+            threads,
+            elapsed.as_secs_f64() * 1000.0 / TICKS as f64,
+            baseline.0.as_secs_f64() / elapsed.as_secs_f64(),
+            hash,
+            if hash == baseline.1 { "" } else { " MISMATCH" },
+        );
+        threads *= 2;
+    }
+}
@@ -0,0 +1,112 @@
+use rand::Rng;
+
+use crate::{brush::Brush, element::Element, tools::Shape, world::World};
+
+/// Something a user did to a world. Every edit made from outside the simulation goes
+/// through `World::apply`, so a session can be recorded and replayed exactly.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    /// Places a fresh particle of `element` at (x, y).
+    Paint {
+        x: i32,
+        y: i32,
+        element: Element,
+    },
+    /// Drags `brush` from `from` to `to`, painting `element` under it.
+    Stroke {
+        from: (i32, i32),
+        to: (i32, i32),
+        element: Element,
+        brush: Brush,
+    },
+    /// Paints `element` over the cells of a shape from one of the geometry tools. Only
+    /// the brush's mode and density are used.
+    Shape {
+        shape: Shape,
+        element: Element,
+        brush: Brush,
+    },
+    /// Fills the whole grid with Air.
+    Clear,
+    /// Ends the current undo entry, e.g. when the mouse button is released at the end of
+    /// a stroke. Edits until then undo together.
+    EndEdit,
+    Undo,
+    Redo,
+    /// Sets how bright the world is away from light sources, see `World::ambient_light`.
+    SetAmbientLight {
+        level: f32,
+    },
+    /// Changes the element the user paints with. Doesn't touch the world, but is kept so
+    /// a replay can show what the user had selected.
+    SelectElement {
+        element: Element,
+    },
+}
+
+impl World {
+    pub fn apply(&mut self, action: &Action) {
+        match *action {
+            Action::Paint { x, y, element } => {
+                let brush = Brush {
+                    radius: 0,
+                    ..Brush::default()
+                };
+                self.paint(&[(x, y)], element, &brush)
+            }
+            Action::Stroke {
+                from,
+                to,
+                element,
+                brush,
+            } => {
+                let cells = brush.stroke(from, to, &self.grid);
+                self.paint(&cells, element, &brush);
+            }
+            Action::Shape {
+                shape,
+                element,
+                brush,
+            } => {
+                // each shape undoes on its own
+                self.history.end_edit();
+                let cells = shape.cells(&self.grid);
+                self.paint(&cells, element, &brush);
+                self.history.end_edit();
+            }
+            Action::Clear => {
+                self.history.end_edit();
+                let cells: Vec<(i32, i32)> = self.grid.iter().map(|(x, y, _)| (x, y)).collect();
+                self.paint(&cells, Element::AIR, &Brush::default());
+                self.history.end_edit();
+            }
+            Action::EndEdit => self.history.end_edit(),
+            Action::Undo => {
+                self.history.undo(&mut self.grid);
+            }
+            Action::Redo => {
+                self.history.redo(&mut self.grid);
+            }
+            Action::SetAmbientLight { level } => self.ambient_light = level.clamp(0.0, 1.0),
+            Action::SelectElement { .. } => {}
+        }
+    }
+
+    /// Places fresh particles of `element` in those of `cells` the brush's mode allows,
+    /// each with the brush's density as its chance. Cells outside the grid are skipped.
+    /// The changes go into the undo history.
+    pub fn paint(&mut self, cells: &[(i32, i32)], element: Element, brush: &Brush) {
+        for &(x, y) in cells {
+            // solid brushes leave the random number generator alone
+            if brush.density < 1.0 && self.rng.gen::<f32>() >= brush.density {
+                continue;
+            }
+            let index = match self.grid.checked_index(x, y) {
+                Some(i) if brush.mode.allows(self.grid.cells()[i].element) => i,
+                _ => continue,
+            };
+            self.history.before_change(&self.grid, index);
+            self.grid.set(x, y, self.elements.spawn(element, 0, x, y));
+        }
+    }
+}
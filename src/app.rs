@@ -0,0 +1,699 @@
+use fallingsand::action::Action;
+use fallingsand::bloom::Bloom;
+use fallingsand::brush::{Brush, BrushMode, BrushShape};
+use fallingsand::camera::Camera;
+use fallingsand::element::{Element, ElementRegistry};
+use fallingsand::grid::Grid;
+use fallingsand::image::Palette;
+use fallingsand::lighting::Lighting;
+use fallingsand::replay::Recording;
+use fallingsand::tools::Shape;
+use fallingsand::world::World;
+
+// use rand::Rng;
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::mouse::MouseButton;
+use sdl2::render::{Texture, TextureCreator};
+use sdl2::video::{FullscreenType, WindowContext};
+
+use crate::render::{
+    create_grid_texture, render_brush_outline, render_cell_outline, render_chunk_overlay,
+    render_particles,
+};
+use crate::settings::{
+    AMBIENT_LIGHT_LEVELS, BLOOM_RADIUS, BLOOM_STRENGTH, FRAME_RATE, LIGHT_FALLOFF,
+    MAX_BRUSH_RADIUS, MAX_TICKS_PER_FRAME, NORMAL_SPEED, QUICKSAVE_PATH, REPLAY_PATH,
+    SCREENSHOT_PATH, SCREENSHOT_SCALE, SPEEDS, SPRAY_DENSITY, TICK_RATE, WINDOW_HEIGHT,
+    WINDOW_WIDTH,
+};
+use crate::ui::{draw_brush_status, draw_particle_options, draw_sim_status};
+
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// What dragging with the mouse does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tool {
+    /// Paints under the brush as it moves.
+    Brush,
+    /// A brush stroke from where the drag started to where it ended.
+    Line,
+    Rect,
+    /// A circle around where the drag started, reaching to where it ended.
+    Circle,
+    /// Replaces the clicked region of one element.
+    Fill,
+}
+
+impl Tool {
+    fn name(self) -> &'static str {
+        match self {
+            Tool::Brush => "brush",
+            Tool::Line => "line",
+            Tool::Rect => "rect",
+            Tool::Circle => "circle",
+            Tool::Fill => "fill",
+        }
+    }
+
+    /// The shape dragging from `from` to `to` draws, for the tools that draw one.
+    fn shape(self, from: (i32, i32), to: (i32, i32), filled: bool) -> Option<Shape> {
+        match self {
+            Tool::Rect => Some(Shape::Rect { from, to, filled }),
+            Tool::Circle => {
+                let (dx, dy) = ((to.0 - from.0) as f32, (to.1 - from.1) as f32);
+                Some(Shape::Circle {
+                    center: from,
+                    radius: (dx * dx + dy * dy).sqrt().round() as i32,
+                    filled,
+                })
+            }
+            Tool::Fill => Some(Shape::Fill { x: to.0, y: to.1 }),
+            Tool::Brush | Tool::Line => None,
+        }
+    }
+}
+
+/// Opens the window and runs the simulation until it is closed. With a `replay`, the
+/// recorded session plays back first and the user takes over once it ends; otherwise
+/// the window starts on an empty world of `size` cells.
+pub fn run(elements: ElementRegistry, size: (i32, i32), replay: Option<Recording>) {
+    // Initialize SDL2 boilerplate
+    let sdl_context = sdl2::init().unwrap();
+
+    let video_subsystem = sdl_context.video().unwrap();
+
+    let window = video_subsystem
+        .window("Falling Sand Simulation", WINDOW_WIDTH, WINDOW_HEIGHT)
+        .position_centered()
+        .resizable()
+        .build()
+        .unwrap();
+
+    let mut canvas = window.into_canvas().build().unwrap();
+    let mut event_pump = sdl_context.event_pump().unwrap();
+
+    let (mut world, mut playback) = match replay {
+        Some(replay) => {
+            let (world, playback) = replay.play();
+            (world, Some(playback))
+        }
+        None => {
+            let grid = Grid::with_size(size.0, size.1);
+            (World::new(grid, elements, rand::random()), None)
+        }
+    };
+    // everything done in this window, F2 writes it out
+    let mut recording = Recording::start(&mut world);
+    let texture_creator: TextureCreator<_> = canvas.texture_creator();
+    // the grid is drawn one pixel per cell into this texture, which is scaled up to the
+    // window size
+    let mut grid_texture: Texture = create_grid_texture(
+        &texture_creator,
+        world.grid.width() as u32,
+        world.grid.height() as u32,
+    );
+    let palette = Palette::from_elements(&world.elements);
+    let mut pixels = Vec::new();
+    let mut camera = Camera::new(
+        world.grid.width(),
+        world.grid.height(),
+        (WINDOW_WIDTH, WINDOW_HEIGHT),
+    );
+
+    // Asset loading
+    //  // Load font
+    let ttf_context = sdl2::ttf::init()
+        .map_err(|e| format!("Failed to init ttf: {}", e))
+        .unwrap();
+    let font_path = "assets/Simple-Bold.ttf";
+    let small_font = ttf_context
+        .load_font(font_path, 16)
+        .map_err(|e| format!("Failed to load font: {}", e))
+        .unwrap();
+    let large_font = ttf_context
+        .load_font(font_path, 24)
+        .map_err(|e| format!("Failed to load font: {}", e))
+        .unwrap();
+
+    //  state
+    let mut current_element = Element::AIR;
+    let mut show_chunks = false;
+    let mut bloom = Bloom::new(BLOOM_RADIUS, BLOOM_STRENGTH);
+    let mut show_bloom = true;
+    let mut lighting = Lighting::new(LIGHT_FALLOFF);
+    let mut brush = Brush::default();
+    let mut tool = Tool::Brush;
+    // whether rects and circles are drawn filled or as outlines
+    let mut filled = true;
+    // the grid cell under the mouse, the button held for the current drag, where the drag
+    // started and where it last painted
+    let mut cursor = (0, 0);
+    // the mouse in screen pixels, and whether the middle button is dragging the camera
+    let mut mouse = (0, 0);
+    let mut panning = false;
+    let mut stroke_button: Option<MouseButton> = None;
+    let mut drag_start = (0, 0);
+    let mut last_paint: Option<(i32, i32)> = None;
+    // simulation timing: ticks run at TICK_RATE * the speed whatever the frame rate, with
+    // the time not yet spent on a tick carried over in the accumulator
+    let mut paused = false;
+    let mut single_step = false;
+    let mut speed = NORMAL_SPEED;
+    let mut accumulator = 0.0;
+    let mut last_frame = Instant::now();
+
+    'running: loop {
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Q),
+                    ..
+                } => break 'running,
+                Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => break 'running,
+                Event::KeyDown {
+                    keycode: Some(Keycode::W),
+                    ..
+                } if playback.is_none() => {
+                    // if we have reached the begining of the list, loop to the end
+                    let count = world.elements.len() as u16;
+                    current_element = Element::from_id((current_element.id() + count - 1) % count);
+                    let action = Action::SelectElement {
+                        element: current_element,
+                    };
+                    perform(&mut world, &mut recording, action);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::S),
+                    ..
+                } if playback.is_none() => {
+                    // if we have reached the end of the list, loop to the begining
+                    let count = world.elements.len() as u16;
+                    current_element = Element::from_id((current_element.id() + 1) % count);
+                    let action = Action::SelectElement {
+                        element: current_element,
+                    };
+                    perform(&mut world, &mut recording, action);
+                }
+                Event::KeyDown {
+                    keycode: Some(keycode @ (Keycode::Z | Keycode::Y)),
+                    keymod,
+                    ..
+                } if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) && playback.is_none() => {
+                    // ctrl+shift+z redoes too
+                    let shift = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+                    let action = if keycode == Keycode::Y || shift {
+                        Action::Redo
+                    } else {
+                        Action::Undo
+                    };
+                    perform(&mut world, &mut recording, action);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Delete),
+                    ..
+                } if playback.is_none() => {
+                    perform(&mut world, &mut recording, Action::Clear);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Space),
+                    ..
+                } => {
+                    paused = !paused;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Period),
+                    ..
+                } if paused => {
+                    single_step = true;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Minus),
+                    ..
+                } => {
+                    speed = speed.saturating_sub(1);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Equals),
+                    ..
+                } => {
+                    speed = (speed + 1).min(SPEEDS.len() - 1);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F3),
+                    ..
+                } => {
+                    show_chunks = !show_chunks;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::G),
+                    ..
+                } => {
+                    show_bloom = !show_bloom;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::L),
+                    ..
+                } if playback.is_none() => {
+                    // the next darker level, wrapping round to fully lit
+                    let level = AMBIENT_LIGHT_LEVELS
+                        .iter()
+                        .copied()
+                        .find(|&l| l < world.ambient_light)
+                        .unwrap_or(AMBIENT_LIGHT_LEVELS[0]);
+                    perform(
+                        &mut world,
+                        &mut recording,
+                        Action::SetAmbientLight { level },
+                    );
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F2),
+                    ..
+                } => {
+                    if let Err(e) = recording.save(REPLAY_PATH, &world) {
+                        eprintln!("{}", e);
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F5),
+                    ..
+                } => {
+                    if let Err(e) = world.save(QUICKSAVE_PATH) {
+                        eprintln!("{}", e);
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F9),
+                    ..
+                } => match World::load(QUICKSAVE_PATH, world.elements.clone(), world.seed) {
+                    Ok(loaded) => {
+                        replace_world(
+                            &mut world,
+                            loaded,
+                            &mut camera,
+                            &mut grid_texture,
+                            &texture_creator,
+                        );
+                        recording = Recording::start(&mut world);
+                        playback = None;
+                    }
+                    Err(e) => eprintln!("{}", e),
+                },
+                Event::KeyDown {
+                    keycode: Some(Keycode::F6),
+                    ..
+                } => {
+                    if let Err(e) =
+                        world
+                            .grid
+                            .export_png(SCREENSHOT_PATH, &palette, SCREENSHOT_SCALE)
+                    {
+                        eprintln!("{}", e);
+                    }
+                }
+                Event::DropFile { filename, .. } => match open_world(&filename, &world) {
+                    Ok(loaded) => {
+                        replace_world(
+                            &mut world,
+                            loaded,
+                            &mut camera,
+                            &mut grid_texture,
+                            &texture_creator,
+                        );
+                        recording = Recording::start(&mut world);
+                        playback = None;
+                    }
+                    Err(e) => eprintln!("{}", e),
+                },
+                Event::KeyDown {
+                    keycode: Some(Keycode::B),
+                    ..
+                } => {
+                    brush.shape = match brush.shape {
+                        BrushShape::Circle => BrushShape::Square,
+                        BrushShape::Square => BrushShape::Circle,
+                    };
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::V),
+                    ..
+                } => {
+                    brush.density = if brush.density < 1.0 {
+                        1.0
+                    } else {
+                        SPRAY_DENSITY
+                    };
+                }
+                Event::KeyDown {
+                    keycode:
+                        Some(
+                            keycode @ (Keycode::Num1
+                            | Keycode::Num2
+                            | Keycode::Num3
+                            | Keycode::Num4
+                            | Keycode::Num5),
+                        ),
+                    ..
+                } => {
+                    tool = match keycode {
+                        Keycode::Num1 => Tool::Brush,
+                        Keycode::Num2 => Tool::Line,
+                        Keycode::Num3 => Tool::Rect,
+                        Keycode::Num4 => Tool::Circle,
+                        _ => Tool::Fill,
+                    };
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F),
+                    ..
+                } => {
+                    filled = !filled;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::M),
+                    ..
+                } => {
+                    brush.mode = match brush.mode {
+                        BrushMode::Overwrite => BrushMode::IntoAir,
+                        BrushMode::IntoAir => match world.grid.get(cursor.0, cursor.1) {
+                            Some(p) => BrushMode::Replace(p.element),
+                            None => BrushMode::Overwrite,
+                        },
+                        BrushMode::Replace(_) => BrushMode::Overwrite,
+                    };
+                }
+                Event::MouseWheel { y, .. } => {
+                    let keymod = sdl_context.keyboard().mod_state();
+                    if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) {
+                        brush.radius = (brush.radius + y).clamp(0, MAX_BRUSH_RADIUS);
+                    } else {
+                        camera.zoom_at(mouse.0, mouse.1, y);
+                        cursor = camera.screen_to_grid(mouse.0, mouse.1);
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::LeftBracket),
+                    ..
+                } => {
+                    brush.radius = (brush.radius - 1).max(0);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::RightBracket),
+                    ..
+                } => {
+                    brush.radius = (brush.radius + 1).min(MAX_BRUSH_RADIUS);
+                }
+                Event::Window {
+                    win_event: WindowEvent::SizeChanged(width, height),
+                    ..
+                } => {
+                    camera.resize((width as u32, height as u32));
+                    cursor = camera.screen_to_grid(mouse.0, mouse.1);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F11),
+                    ..
+                } => {
+                    let window = canvas.window_mut();
+                    let fullscreen = match window.fullscreen_state() {
+                        FullscreenType::Off => FullscreenType::Desktop,
+                        _ => FullscreenType::Off,
+                    };
+                    if let Err(e) = window.set_fullscreen(fullscreen) {
+                        eprintln!("Failed to toggle fullscreen: {}", e);
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Home),
+                    ..
+                } => {
+                    camera.fit(world.grid.width(), world.grid.height());
+                    cursor = camera.screen_to_grid(mouse.0, mouse.1);
+                }
+                Event::MouseButtonDown {
+                    mouse_btn: MouseButton::Middle,
+                    ..
+                } => {
+                    panning = true;
+                }
+                Event::MouseButtonUp {
+                    mouse_btn: MouseButton::Middle,
+                    ..
+                } => {
+                    panning = false;
+                }
+                Event::MouseButtonDown {
+                    mouse_btn: button @ (MouseButton::Left | MouseButton::Right),
+                    x,
+                    y,
+                    ..
+                } => {
+                    stroke_button = Some(button);
+                    mouse = (x, y);
+                    cursor = camera.screen_to_grid(x, y);
+                    drag_start = cursor;
+                    if playback.is_none() {
+                        let (element, brush) = for_button(button, current_element, brush);
+                        match tool {
+                            Tool::Brush => {
+                                let action = Action::Stroke {
+                                    from: cursor,
+                                    to: cursor,
+                                    element,
+                                    brush,
+                                };
+                                perform(&mut world, &mut recording, action);
+                                last_paint = Some(cursor);
+                            }
+                            Tool::Fill => {
+                                if let Some(shape) = tool.shape(cursor, cursor, filled) {
+                                    let action = Action::Shape {
+                                        shape,
+                                        element,
+                                        brush,
+                                    };
+                                    perform(&mut world, &mut recording, action);
+                                }
+                            }
+                            // the rest draw when the button is released
+                            Tool::Line | Tool::Rect | Tool::Circle => {}
+                        }
+                    }
+                }
+                Event::MouseMotion {
+                    x, y, xrel, yrel, ..
+                } => {
+                    if panning {
+                        camera.pan(xrel, yrel);
+                    }
+                    mouse = (x, y);
+                    cursor = camera.screen_to_grid(x, y);
+                    if let (Some(button), None, Tool::Brush) = (stroke_button, &playback, tool) {
+                        // join up with the last position so fast strokes don't leave gaps
+                        let (element, brush) = for_button(button, current_element, brush);
+                        let action = Action::Stroke {
+                            from: last_paint.unwrap_or(cursor),
+                            to: cursor,
+                            element,
+                            brush,
+                        };
+                        perform(&mut world, &mut recording, action);
+                        last_paint = Some(cursor);
+                    }
+                }
+                Event::MouseButtonUp { mouse_btn, .. } if stroke_button == Some(mouse_btn) => {
+                    if playback.is_none() {
+                        let (element, brush) = for_button(mouse_btn, current_element, brush);
+                        let action = match tool {
+                            Tool::Line => Some(Action::Stroke {
+                                from: drag_start,
+                                to: cursor,
+                                element,
+                                brush,
+                            }),
+                            Tool::Rect | Tool::Circle => tool
+                                .shape(drag_start, cursor, filled)
+                                .map(|shape| Action::Shape {
+                                    shape,
+                                    element,
+                                    brush,
+                                }),
+                            Tool::Brush | Tool::Fill => None,
+                        };
+                        if let Some(action) = action {
+                            perform(&mut world, &mut recording, action);
+                        }
+                        // everything since the button went down undoes as one
+                        perform(&mut world, &mut recording, Action::EndEdit);
+                    }
+                    stroke_button = None;
+                    last_paint = None;
+                }
+                _ => {}
+            }
+        }
+
+        // update zone
+        let now = Instant::now();
+        let tick_length = 1.0 / TICK_RATE;
+        let mut ticks = 0;
+        if paused {
+            accumulator = 0.0;
+            if single_step {
+                ticks = 1;
+                single_step = false;
+            }
+        } else {
+            accumulator += (now - last_frame).as_secs_f64() * SPEEDS[speed];
+            while accumulator >= tick_length && ticks < MAX_TICKS_PER_FRAME {
+                accumulator -= tick_length;
+                ticks += 1;
+            }
+            // too far behind to catch up; drop the backlog instead of falling further behind
+            accumulator = accumulator.min(tick_length);
+        }
+        last_frame = now;
+
+        for _ in 0..ticks {
+            if let Some(replay) = &mut playback {
+                for (_, action) in replay.advance() {
+                    if let Action::SelectElement { element } = *action {
+                        current_element = element;
+                    }
+                    perform(&mut world, &mut recording, *action);
+                }
+                if replay.is_finished() {
+                    if let Err(e) = replay.check_end(&world) {
+                        eprintln!("{}", e);
+                    }
+                    playback = None;
+                }
+            }
+            world.step();
+            recording.tick();
+        }
+
+        // render zone
+        render_particles(
+            &mut canvas,
+            &camera,
+            &mut grid_texture,
+            &world,
+            &mut lighting,
+            show_bloom.then_some(&mut bloom),
+            &mut pixels,
+        );
+        if show_chunks {
+            render_chunk_overlay(&mut canvas, &camera, &world);
+        }
+        match (tool, stroke_button) {
+            (Tool::Brush, _) | (Tool::Line, None) => {
+                render_brush_outline(&mut canvas, &camera, &brush, cursor.0, cursor.1)
+            }
+            // preview the line or shape being dragged out
+            (Tool::Line, Some(_)) => render_cell_outline(
+                &mut canvas,
+                &camera,
+                &brush.stroke(drag_start, cursor, &world.grid),
+            ),
+            (Tool::Rect | Tool::Circle, Some(_)) => {
+                if let Some(shape) = tool.shape(drag_start, cursor, false) {
+                    render_cell_outline(&mut canvas, &camera, &shape.cells(&world.grid));
+                }
+            }
+            _ => {}
+        }
+        // draw_particle_count(&mut canvas, &small_font, &particles, &texture_creator);
+        draw_particle_options(
+            &mut canvas,
+            &small_font,
+            &large_font,
+            &world.elements,
+            &current_element,
+            &texture_creator,
+        );
+        draw_sim_status(
+            &mut canvas,
+            &small_font,
+            paused,
+            SPEEDS[speed],
+            &texture_creator,
+        );
+        draw_brush_status(
+            &mut canvas,
+            &small_font,
+            tool.name(),
+            filled,
+            &brush,
+            &world.elements,
+            &texture_creator,
+        );
+
+        canvas.present();
+        // std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 30));
+        let frame_length = Duration::from_secs_f64(1.0 / FRAME_RATE);
+        if let Some(rest) = frame_length.checked_sub(now.elapsed()) {
+            std::thread::sleep(rest);
+        }
+    }
+}
+
+/// Applies an action to the world and adds it to the recording.
+fn perform(world: &mut World, recording: &mut Recording, action: Action) {
+    world.apply(&action);
+    recording.record(action);
+}
+
+/// What a tool used with `button` paints, and with which brush: the left button paints
+/// `element` and the right button erases.
+fn for_button(button: MouseButton, element: Element, brush: Brush) -> (Element, Brush) {
+    match button {
+        MouseButton::Right => {
+            // erasing only into Air would do nothing
+            let mode = match brush.mode {
+                BrushMode::IntoAir => BrushMode::Overwrite,
+                mode => mode,
+            };
+            (Element::AIR, Brush { mode, ..brush })
+        }
+        _ => (element, brush),
+    }
+}
+
+/// Reads a dropped file into a world with the same elements and seed as `current`: PNGs
+/// are imported with the element colours, anything else is treated as a world file.
+fn open_world(path: &str, current: &World) -> Result<World, String> {
+    let path = Path::new(path);
+    let elements = &current.elements;
+    if path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("png"))
+    {
+        let grid = Grid::import_png(path, &Palette::from_elements(elements), elements)?;
+        Ok(World::new(grid, elements.clone(), current.seed))
+    } else {
+        World::load(path, elements.clone(), current.seed)
+    }
+}
+
+fn replace_world<'a>(
+    world: &mut World,
+    loaded: World,
+    camera: &mut Camera,
+    grid_texture: &mut Texture<'a>,
+    texture_creator: &'a TextureCreator<WindowContext>,
+) {
+    // the new world may be a different size than the current one
+    let (width, height) = (loaded.grid.width(), loaded.grid.height());
+    if (width, height) != (world.grid.width(), world.grid.height()) {
+        *grid_texture = create_grid_texture(texture_creator, width as u32, height as u32);
+        camera.fit(width, height);
+    }
+    *world = loaded;
+}
@@ -0,0 +1,137 @@
+/*
+    Bloom: emissive and burning particles glow and light up the cells around them. The
+    colours of those cells are copied into a light buffer, blurred with two passes of a separable
+    box blur (close enough to a gaussian), and added over the shaded pixels. Everything
+    works on the grid-sized RGBA buffer from `World::fill_rgba`, one pixel per cell.
+*/
+
+use crate::world::World;
+
+pub struct Bloom {
+    /// How far the glow spreads, in cells.
+    pub radius: usize,
+    /// Multiplies the blurred light before it is added.
+    pub strength: f32,
+    light: Vec<[f32; 3]>,
+    scratch: Vec<[f32; 3]>,
+}
+
+impl Bloom {
+    pub fn new(radius: usize, strength: f32) -> Bloom {
+        Bloom {
+            radius,
+            strength,
+            light: Vec::new(),
+            scratch: Vec::new(),
+        }
+    }
+
+    /// Adds the glow of the world's emissive and burning cells to `pixels`, which holds the world as
+    /// drawn by `World::fill_rgba`.
+    pub fn apply(&mut self, world: &World, pixels: &mut [u8]) {
+        let width = world.grid.width() as usize;
+        let height = world.grid.height() as usize;
+
+        self.light.clear();
+        self.light.resize(width * height, [0.0; 3]);
+        let mut any = false;
+        for (i, p) in world.grid.cells().iter().enumerate() {
+            if world.elements.get(p.element).gives_light(p) {
+                let c = &pixels[i * 4..i * 4 + 3];
+                self.light[i] = [c[0] as f32, c[1] as f32, c[2] as f32];
+                any = true;
+            }
+        }
+        if !any {
+            return;
+        }
+
+        self.scratch.resize(width * height, [0.0; 3]);
+        for _ in 0..2 {
+            // rows into scratch, then columns back into light
+            blur(
+                &self.light,
+                &mut self.scratch,
+                width,
+                height,
+                1,
+                width,
+                self.radius,
+            );
+            blur(
+                &self.scratch,
+                &mut self.light,
+                height,
+                width,
+                width,
+                1,
+                self.radius,
+            );
+        }
+
+        for (pixel, light) in pixels.chunks_exact_mut(4).zip(&self.light) {
+            for (c, l) in pixel.iter_mut().zip(light) {
+                *c = (*c as f32 + l * self.strength).min(255.0) as u8;
+            }
+            // the glow shows over Air too
+            pixel[3] = pixel[3].max(pixel[0]).max(pixel[1]).max(pixel[2]);
+        }
+    }
+}
+
+/// Box blurs each of `lines` lines of `len` values, where a line starts every
+/// `line_stride` values and steps by `step`. Values past the ends count as 0.
+fn blur(
+    src: &[[f32; 3]],
+    dst: &mut [[f32; 3]],
+    len: usize,
+    lines: usize,
+    step: usize,
+    line_stride: usize,
+    radius: usize,
+) {
+    let scale = 1.0 / (2 * radius + 1) as f32;
+    for line in 0..lines {
+        let at = |i: usize| line * line_stride + i * step;
+        let mut sum = [0.0; 3];
+        for i in 0..radius.min(len) {
+            add(&mut sum, &src[at(i)], 1.0);
+        }
+        for i in 0..len {
+            // the window is i - radius..=i + radius
+            if i + radius < len {
+                add(&mut sum, &src[at(i + radius)], 1.0);
+            }
+            if i > radius {
+                add(&mut sum, &src[at(i - radius - 1)], -1.0);
+            }
+            dst[at(i)] = sum.map(|s| s * scale);
+        }
+    }
+}
+
+fn add(sum: &mut [f32; 3], value: &[f32; 3], sign: f32) {
+    for (s, v) in sum.iter_mut().zip(value) {
+        *s += v * sign;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{element::ElementRegistry, world::tests::world_with};
+
+    #[test]
+    fn burning_particles_glow() {
+        let mut world = world_with(ElementRegistry::builtin(), &[(4, 4, "Wood")]);
+        let glow_next_to_wood = |world: &World| {
+            let mut pixels = Vec::new();
+            world.fill_rgba(&mut pixels);
+            Bloom::new(2, 1.0).apply(world, &mut pixels);
+            pixels[world.grid.index(5, 4) * 4]
+        };
+        assert_eq!(glow_next_to_wood(&world), 0);
+        world.grid.get_mut(4, 4).unwrap().burning = 10;
+        assert!(glow_next_to_wood(&world) > 0);
+    }
+}
@@ -0,0 +1,150 @@
+/*
+    The camera decides which part of the grid is on screen. Cells are drawn as squares of
+    a whole number of pixels (the zoom), so the nearest-neighbour scaling stays crisp, and
+    the grid's top-left corner sits at `offset` on screen. Panning moves the offset by
+    whole pixels, and zooming keeps the cell under the mouse where it is. Fitting the
+    grid into the view centres it, which leaves black bars along the sides that don't
+    divide evenly into cells. Resizing the view keeps the zoom and the point at the
+    centre of the view, so the picture only moves by as much as the centre does.
+
+    The camera only does the maths, in plain screen and cell coordinates, so it can be
+    used and tested without a window.
+*/
+
+/// Zoom range for the scroll wheel, in screen pixels per cell.
+pub const MIN_ZOOM: i32 = 1;
+pub const MAX_ZOOM: i32 = 64;
+
+pub struct Camera {
+    /// Screen position of the top-left corner of cell (0, 0).
+    pub offset: (i32, i32),
+    /// Pixels per cell.
+    pub zoom: i32,
+    /// Size of the area the grid is drawn into.
+    view: (u32, u32),
+}
+
+impl Camera {
+    /// A camera showing the whole of a `grid_width` x `grid_height` grid in a view of the
+    /// given size.
+    pub fn new(grid_width: i32, grid_height: i32, view: (u32, u32)) -> Camera {
+        let mut camera = Camera {
+            offset: (0, 0),
+            zoom: MIN_ZOOM,
+            view,
+        };
+        camera.fit(grid_width, grid_height);
+        camera
+    }
+
+    /// Zooms in as far as the whole grid still fits, and centres it. A grid bigger than
+    /// the view in cells is shown at one pixel per cell, cropped around its centre.
+    pub fn fit(&mut self, grid_width: i32, grid_height: i32) {
+        let zoom_x = self.view.0 as i32 / grid_width.max(1);
+        let zoom_y = self.view.1 as i32 / grid_height.max(1);
+        self.zoom = zoom_x.min(zoom_y).clamp(MIN_ZOOM, MAX_ZOOM);
+        self.offset = (
+            (self.view.0 as i32 - grid_width * self.zoom) / 2,
+            (self.view.1 as i32 - grid_height * self.zoom) / 2,
+        );
+    }
+
+    /// Changes the size of the area the grid is drawn into, e.g. when the window is
+    /// resized, keeping the zoom and the grid position at the centre of the view.
+    pub fn resize(&mut self, view: (u32, u32)) {
+        let centre = |view: (u32, u32)| (view.0 as i32 / 2, view.1 as i32 / 2);
+        let (old, new) = (centre(self.view), centre(view));
+        self.pan(new.0 - old.0, new.1 - old.1);
+        self.view = view;
+    }
+
+    /// Moves the grid by (dx, dy) screen pixels.
+    pub fn pan(&mut self, dx: i32, dy: i32) {
+        self.offset = (self.offset.0 + dx, self.offset.1 + dy);
+    }
+
+    /// Zooms in by `steps` (out if negative), keeping the point under screen position
+    /// (x, y) in place.
+    pub fn zoom_at(&mut self, x: i32, y: i32, steps: i32) {
+        let zoom = (self.zoom + steps).clamp(MIN_ZOOM, MAX_ZOOM);
+        // the grid position under the mouse, in fractions of a cell
+        let gx = (x - self.offset.0) as f32 / self.zoom as f32;
+        let gy = (y - self.offset.1) as f32 / self.zoom as f32;
+        self.offset = (
+            x - (gx * zoom as f32).round() as i32,
+            y - (gy * zoom as f32).round() as i32,
+        );
+        self.zoom = zoom;
+    }
+
+    /// The grid cell under screen position (x, y), which may be outside the grid.
+    pub fn screen_to_grid(&self, x: i32, y: i32) -> (i32, i32) {
+        (
+            (x - self.offset.0).div_euclid(self.zoom),
+            (y - self.offset.1).div_euclid(self.zoom),
+        )
+    }
+
+    /// The screen area covering the `width` x `height` cells from cell (x, y), as x, y,
+    /// width and height in pixels.
+    pub fn cells_rect(&self, x: i32, y: i32, width: i32, height: i32) -> (i32, i32, u32, u32) {
+        (
+            self.offset.0 + x * self.zoom,
+            self.offset.1 + y * self.zoom,
+            (width * self.zoom) as u32,
+            (height * self.zoom) as u32,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn screen_to_grid_rounds_down_left_of_the_grid() {
+        let mut camera = Camera::new(10, 10, (100, 100));
+        assert_eq!(camera.zoom, 10);
+        camera.offset = (-25, -5);
+        assert_eq!(camera.screen_to_grid(0, 0), (2, 0));
+        camera.offset = (5, 5);
+        // one pixel left of and above the grid is cell -1, not 0
+        assert_eq!(camera.screen_to_grid(4, 4), (-1, -1));
+        assert_eq!(camera.screen_to_grid(-6, 5), (-2, 0));
+    }
+
+    #[test]
+    fn zoom_keeps_the_cell_under_the_cursor() {
+        let mut camera = Camera::new(64, 64, (256, 256));
+        let cursor = (101, 37);
+        let cell = camera.screen_to_grid(cursor.0, cursor.1);
+        for steps in [3, 10, -5, -64, 64] {
+            camera.zoom_at(cursor.0, cursor.1, steps);
+            assert_eq!(camera.screen_to_grid(cursor.0, cursor.1), cell);
+        }
+        assert_eq!(camera.zoom, MAX_ZOOM);
+    }
+
+    #[test]
+    fn resize_keeps_the_view_centre() {
+        let mut camera = Camera::new(20, 20, (200, 100));
+        camera.zoom_at(40, 60, 2);
+        let zoom = camera.zoom;
+        let centre = camera.screen_to_grid(100, 50);
+        camera.resize((400, 300));
+        assert_eq!(camera.zoom, zoom);
+        assert_eq!(camera.screen_to_grid(200, 150), centre);
+        camera.resize((50, 40));
+        assert_eq!(camera.screen_to_grid(25, 20), centre);
+    }
+
+    #[test]
+    fn fit_crops_a_grid_bigger_than_the_view() {
+        let camera = Camera::new(300, 150, (100, 100));
+        assert_eq!(camera.zoom, MIN_ZOOM);
+        assert_eq!(camera.offset, (-100, -25));
+        // the middle of the grid is in the middle of the view
+        assert_eq!(camera.screen_to_grid(50, 50), (150, 75));
+        assert_eq!(camera.cells_rect(0, 0, 300, 150), (-100, -25, 300, 150));
+    }
+}
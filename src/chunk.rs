@@ -0,0 +1,260 @@
+/*
+    The grid is divided into CHUNK_SIZE x CHUNK_SIZE chunks, and each chunk keeps a dirty
+    rect: the bounding box of every cell that changed in it, grown by one cell so that the
+    neighbours of a change get a look too. A step only visits the cells inside the rects
+    recorded during the previous step; a chunk whose rect is empty is asleep and skipped.
+
+    Changes near a chunk edge grow the rect of the neighbouring chunk as well, which is
+    how activity wakes the chunks around it.
+
+    Chunks are also the unit of parallel work, see step_particles.
+*/
+
+/// Width and height of a chunk, in cells.
+pub const CHUNK_SIZE: i32 = 32;
+
+/// An inclusive rectangle of cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DirtyRect {
+    pub x0: i32,
+    pub y0: i32,
+    pub x1: i32,
+    pub y1: i32,
+}
+
+impl DirtyRect {
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        (self.x0..=self.x1).contains(&x) && (self.y0..=self.y1).contains(&y)
+    }
+
+    pub fn width(&self) -> i32 {
+        self.x1 - self.x0 + 1
+    }
+
+    pub fn height(&self) -> i32 {
+        self.y1 - self.y0 + 1
+    }
+
+    /// The rect grown by `n` cells on every side.
+    pub fn grow(self, n: i32) -> DirtyRect {
+        DirtyRect {
+            x0: self.x0 - n,
+            y0: self.y0 - n,
+            x1: self.x1 + n,
+            y1: self.y1 + n,
+        }
+    }
+
+    /// The rect moved by (dx, dy).
+    pub fn offset(self, dx: i32, dy: i32) -> DirtyRect {
+        DirtyRect {
+            x0: self.x0 + dx,
+            y0: self.y0 + dy,
+            x1: self.x1 + dx,
+            y1: self.y1 + dy,
+        }
+    }
+
+    fn union(self, other: DirtyRect) -> DirtyRect {
+        DirtyRect {
+            x0: self.x0.min(other.x0),
+            y0: self.y0.min(other.y0),
+            x1: self.x1.max(other.x1),
+            y1: self.y1.max(other.y1),
+        }
+    }
+
+    pub fn intersect(self, other: DirtyRect) -> Option<DirtyRect> {
+        let r = DirtyRect {
+            x0: self.x0.max(other.x0),
+            y0: self.y0.max(other.y0),
+            x1: self.x1.min(other.x1),
+            y1: self.y1.min(other.y1),
+        };
+        if r.x0 <= r.x1 && r.y0 <= r.y1 {
+            Some(r)
+        } else {
+            None
+        }
+    }
+}
+
+/// Per-chunk dirty rects for a grid of the given size.
+#[derive(Debug, Clone)]
+pub struct ChunkMap {
+    width: i32,
+    height: i32,
+    chunks_x: i32,
+    chunks_y: i32,
+    /// Rects being worked through by the current step.
+    active: Vec<Option<DirtyRect>>,
+    /// Rects collected for the next step.
+    dirty: Vec<Option<DirtyRect>>,
+}
+
+impl ChunkMap {
+    /// A chunk map for a `width` x `height` grid, with every chunk awake.
+    pub fn new(width: i32, height: i32) -> ChunkMap {
+        let mut map = ChunkMap::asleep(width, height);
+        map.wake_all();
+        map
+    }
+
+    /// A chunk map for a `width` x `height` grid, with nothing marked.
+    pub fn asleep(width: i32, height: i32) -> ChunkMap {
+        let chunks_x = (width + CHUNK_SIZE - 1) / CHUNK_SIZE;
+        let chunks_y = (height + CHUNK_SIZE - 1) / CHUNK_SIZE;
+        let count = (chunks_x * chunks_y) as usize;
+        ChunkMap {
+            width,
+            height,
+            chunks_x,
+            chunks_y,
+            active: vec![None; count],
+            dirty: vec![None; count],
+        }
+    }
+
+    pub fn chunks_x(&self) -> i32 {
+        self.chunks_x
+    }
+
+    pub fn chunks_y(&self) -> i32 {
+        self.chunks_y
+    }
+
+    /// The cells covered by chunk (cx, cy).
+    pub fn bounds(&self, cx: i32, cy: i32) -> DirtyRect {
+        DirtyRect {
+            x0: cx * CHUNK_SIZE,
+            y0: cy * CHUNK_SIZE,
+            x1: ((cx + 1) * CHUNK_SIZE).min(self.width) - 1,
+            y1: ((cy + 1) * CHUNK_SIZE).min(self.height) - 1,
+        }
+    }
+
+    /// The part of chunk (cx, cy) the current step should visit, None if it is asleep.
+    pub fn active_rect(&self, cx: i32, cy: i32) -> Option<DirtyRect> {
+        self.active[(cy * self.chunks_x + cx) as usize]
+    }
+
+    pub fn is_awake(&self, cx: i32, cy: i32) -> bool {
+        self.active_rect(cx, cy).is_some()
+    }
+
+    /// Every cell of the grid.
+    pub fn bounds_of_world(&self) -> DirtyRect {
+        DirtyRect {
+            x0: 0,
+            y0: 0,
+            x1: self.width - 1,
+            y1: self.height - 1,
+        }
+    }
+
+    /// Records a change to cell (x, y), so it and its neighbours get visited next step.
+    pub fn mark_dirty(&mut self, x: i32, y: i32) {
+        self.mark_rect(DirtyRect {
+            x0: x - 1,
+            y0: y - 1,
+            x1: x + 1,
+            y1: y + 1,
+        });
+    }
+
+    /// Records a change to every cell in `rect`.
+    pub fn mark_rect(&mut self, rect: DirtyRect) {
+        let rect = match rect.intersect(self.bounds_of_world()) {
+            Some(rect) => rect,
+            None => return,
+        };
+        for cy in rect.y0 / CHUNK_SIZE..=rect.y1 / CHUNK_SIZE {
+            for cx in rect.x0 / CHUNK_SIZE..=rect.x1 / CHUNK_SIZE {
+                if let Some(part) = rect.intersect(self.bounds(cx, cy)) {
+                    let slot = &mut self.dirty[(cy * self.chunks_x + cx) as usize];
+                    *slot = Some(slot.map_or(part, |r| r.union(part)));
+                }
+            }
+        }
+    }
+
+    /// The changes collected for the next step so far.
+    pub fn dirty_rects(&self) -> impl Iterator<Item = DirtyRect> + '_ {
+        self.dirty.iter().flatten().copied()
+    }
+
+    /// Marks the whole grid as changed, for after bulk edits.
+    pub fn wake_all(&mut self) {
+        for cy in 0..self.chunks_y {
+            for cx in 0..self.chunks_x {
+                self.dirty[(cy * self.chunks_x + cx) as usize] = Some(self.bounds(cx, cy));
+            }
+        }
+    }
+
+    /// Starts a step: the changes collected so far become the work for this step, and
+    /// collection starts over for the next one.
+    pub fn begin_step(&mut self) {
+        std::mem::swap(&mut self.active, &mut self.dirty);
+        self.dirty.iter_mut().for_each(|r| *r = None);
+    }
+
+    /// Number of chunks the current step is visiting.
+    pub fn awake_count(&self) -> usize {
+        self.active.iter().filter(|r| r.is_some()).count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        element::ElementRegistry,
+        grid::Grid,
+        particle::Particle,
+        world::{tests::stepped, World},
+    };
+
+    fn rect(x0: i32, y0: i32, x1: i32, y1: i32) -> DirtyRect {
+        DirtyRect { x0, y0, x1, y1 }
+    }
+
+    #[test]
+    fn settled_sand_falls_asleep() {
+        let elements = ElementRegistry::builtin();
+        let sand = elements.by_name("Sand").unwrap();
+        let mut grid = Grid::with_size(64, 64);
+        for y in 0..10 {
+            for x in 20..44 {
+                grid.set(x, y, elements.spawn(sand, 0, x, y));
+            }
+        }
+        let world = stepped(World::new(grid, elements, 0), 400);
+        assert_eq!(world.grid.chunks().awake_count(), 0);
+    }
+
+    #[test]
+    fn changes_at_a_chunk_edge_wake_the_neighbour() {
+        let mut grid = Grid::with_size(64, 64);
+        grid.begin_step();
+        grid.begin_step();
+        assert_eq!(grid.chunks().awake_count(), 0);
+        grid.set(31, 5, Particle::default());
+        grid.begin_step();
+        assert!(grid.chunks().is_awake(0, 0) && grid.chunks().is_awake(1, 0));
+        assert!(!grid.chunks().is_awake(0, 1));
+        assert_eq!(grid.chunks().active_rect(0, 0), Some(rect(30, 4, 31, 6)));
+        assert_eq!(grid.chunks().active_rect(1, 0), Some(rect(32, 4, 32, 6)));
+    }
+
+    #[test]
+    fn paste_grows_the_changes_in_the_part_by_a_cell() {
+        let mut grid = Grid::with_size(64, 64);
+        grid.begin_step();
+        let mut part = grid.extract(rect(8, 8, 15, 15));
+        part.set(2, 2, Particle::default());
+        grid.paste(&part, 8, 8);
+        let dirty: Vec<_> = grid.chunks().dirty_rects().collect();
+        assert_eq!(dirty, vec![rect(8, 8, 12, 12)]);
+    }
+}
@@ -0,0 +1,159 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+use fallingsand::element::ElementRegistry;
+use fallingsand::grid::Grid;
+use fallingsand::image::Palette;
+use fallingsand::replay::Recording;
+use fallingsand::world::World;
+
+use crate::settings::{GRID_HEIGHT, GRID_WIDTH, MAX_GRID_SIZE};
+
+/// Falling sand simulation. Opens a window when run without a subcommand.
+#[derive(Parser)]
+#[command(version)]
+pub struct Args {
+    /// Element definitions to load, instead of the ones built in from assets/elements.ron.
+    #[arg(long, global = true)]
+    pub elements: Option<PathBuf>,
+
+    /// Width in cells of the empty world the window starts with.
+    #[arg(long, default_value_t = GRID_WIDTH, value_parser = clap::value_parser!(i32).range(1..=MAX_GRID_SIZE))]
+    pub width: i32,
+    /// Height in cells of the empty world the window starts with.
+    #[arg(long, default_value_t = GRID_HEIGHT, value_parser = clap::value_parser!(i32).range(1..=MAX_GRID_SIZE))]
+    pub height: i32,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Steps a world file for a number of ticks without opening a window.
+    Run {
+        #[arg(long)]
+        input: PathBuf,
+        #[arg(long)]
+        ticks: u32,
+        /// Seeds the simulation's random number generator; the same seed gives the same result.
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+        #[arg(long)]
+        output: PathBuf,
+        /// Also write the world every N ticks, as snapshot-dir/tick_<tick>.bin.
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+        snapshot_every: Option<u32>,
+        #[arg(long, default_value = "snapshots")]
+        snapshot_dir: PathBuf,
+        /// Write each snapshot as a PNG next to the world file.
+        #[arg(long, requires = "snapshot_every")]
+        snapshot_png: bool,
+        /// Colour overrides for the snapshot PNGs, see src/image.rs.
+        #[arg(long)]
+        palette: Option<PathBuf>,
+    },
+    /// Plays back a session recorded with F2, in the window or, with --output, headless.
+    Replay {
+        #[arg(long)]
+        input: PathBuf,
+        /// Write the world as it was at the end of the recording instead of showing it.
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Writes a world file as a PNG image.
+    ExportPng {
+        #[arg(long)]
+        input: PathBuf,
+        #[arg(long)]
+        output: PathBuf,
+        /// Size of each cell in pixels.
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=64))]
+        scale: u32,
+        /// Colour overrides, see src/image.rs.
+        #[arg(long)]
+        palette: Option<PathBuf>,
+    },
+    /// Converts a PNG image to a world file, one cell per pixel.
+    ImportPng {
+        #[arg(long)]
+        input: PathBuf,
+        #[arg(long)]
+        output: PathBuf,
+        /// Colour overrides, see src/image.rs.
+        #[arg(long)]
+        palette: Option<PathBuf>,
+    },
+}
+
+pub fn run(command: Command, elements: &ElementRegistry) -> Result<(), String> {
+    match command {
+        Command::Run {
+            input,
+            ticks,
+            seed,
+            output,
+            snapshot_every,
+            snapshot_dir,
+            snapshot_png,
+            palette,
+        } => {
+            let palette = load_palette(palette, elements)?;
+            let mut world = World::load(input, elements.clone(), seed)?;
+            if snapshot_every.is_some() {
+                std::fs::create_dir_all(&snapshot_dir)
+                    .map_err(|e| format!("Failed to create {}: {}", snapshot_dir.display(), e))?;
+            }
+            for tick in 1..=ticks {
+                world.step();
+                if snapshot_every.is_some_and(|n| tick % n == 0) {
+                    let path = snapshot_dir.join(format!("tick_{:06}.bin", tick));
+                    world.save(&path)?;
+                    if snapshot_png {
+                        world
+                            .grid
+                            .export_png(path.with_extension("png"), &palette, 1)?;
+                    }
+                }
+            }
+            world.save(output)
+        }
+        Command::Replay { input, output } => {
+            let output = output.ok_or("replay without --output needs the window")?;
+            let world = Recording::load(input, elements)?.replay()?;
+            world.save(output)
+        }
+        Command::ExportPng {
+            input,
+            output,
+            scale,
+            palette,
+        } => {
+            let palette = load_palette(palette, elements)?;
+            Grid::load(input, elements)?.export_png(output, &palette, scale)
+        }
+        Command::ImportPng {
+            input,
+            output,
+            palette,
+        } => {
+            let palette = load_palette(palette, elements)?;
+            Grid::import_png(input, &palette, elements)?.save(output, elements)
+        }
+    }
+}
+
+/// The elements from `path`, or the builtin ones if no path was given.
+pub fn load_elements(path: Option<PathBuf>) -> Result<ElementRegistry, String> {
+    match path {
+        Some(path) => ElementRegistry::load(path),
+        None => Ok(ElementRegistry::builtin()),
+    }
+}
+
+fn load_palette(path: Option<PathBuf>, elements: &ElementRegistry) -> Result<Palette, String> {
+    match path {
+        Some(path) => Palette::load(path, elements),
+        None => Ok(Palette::from_elements(elements)),
+    }
+}
@@ -0,0 +1,43 @@
+/// An RGBA colour, independent of any rendering backend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Color {
+    pub const fn rgba(r: u8, g: u8, b: u8, a: u8) -> Color {
+        Color { r, g, b, a }
+    }
+
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Color {
+        Color::rgba(r, g, b, 255)
+    }
+}
+
+impl Color {
+    /// Adds `amount` to each colour channel, keeping alpha.
+    pub fn brightened(self, amount: i32) -> Color {
+        let channel = |c: u8| (c as i32 + amount).clamp(0, 255) as u8;
+        Color::rgba(channel(self.r), channel(self.g), channel(self.b), self.a)
+    }
+
+    /// Multiplies each colour channel by `factor`, keeping alpha.
+    pub fn scaled(self, factor: f32) -> Color {
+        let channel = |c: u8| (c as f32 * factor).clamp(0.0, 255.0) as u8;
+        Color::rgba(channel(self.r), channel(self.g), channel(self.b), self.a)
+    }
+
+    /// Blends `t` of the way from this colour to `other`, keeping this colour's alpha.
+    pub fn mix(self, other: Color, t: f32) -> Color {
+        let channel = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+        Color::rgba(
+            channel(self.r, other.r),
+            channel(self.g, other.g),
+            channel(self.b, other.b),
+            self.a,
+        )
+    }
+}
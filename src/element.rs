@@ -1,55 +1,612 @@
-use std::fmt;
-
-use enum_iterator::Sequence;
-use sdl2::pixels::Color;
-
-#[derive(Debug, PartialEq, Sequence, Clone, Copy)]
-pub enum Element {
-    Air,
-    Sand,
-    Oil,
-    Water,
-    Fire,
-    Smoke,
-    Steam,
-    Wood,
-    Wall,
-    Ice,
-    Lava,
-}
-
-impl fmt::Display for Element {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            Element::Air => write!(f, "Air"),
-            Element::Sand => write!(f, "Sand"),
-            Element::Oil => write!(f, "Oil"),
-            Element::Water => write!(f, "Water"),
-            Element::Fire => write!(f, "Fire"),
-            Element::Smoke => write!(f, "Smoke"),
-            Element::Steam => write!(f, "Steam"),
-            Element::Wood => write!(f, "Wood"),
-            Element::Wall => write!(f, "Wall"),
-            Element::Ice => write!(f, "Ice"),
-            Element::Lava => write!(f, "Lava"),
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::color::Color;
+use crate::image::Palette;
+use crate::particle::{color_seed, Particle, ROOM_TEMPERATURE};
+
+/// An element, identified by its position in the `ElementRegistry` it was loaded from.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Default)]
+pub struct Element(u16);
+
+impl Element {
+    /// Air is always the first entry of a registry.
+    pub const AIR: Element = Element(0);
+
+    pub fn from_id(id: u16) -> Element {
+        Element(id)
+    }
+
+    pub fn id(self) -> u16 {
+        self.0
+    }
+}
+
+/// How an element moves each tick.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Deserialize)]
+pub enum Movement {
+    #[default]
+    None,
+    /// Falls straight down, or diagonally down.
+    Powder,
+    /// Falls like a powder, otherwise spreads left and right.
+    Liquid,
+    /// Rises, otherwise spreads left and right.
+    Gas,
+}
+
+/// Effects drawn on top of an element's shades, see `ElementDef::shade`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Deserialize)]
+pub enum Shading {
+    #[default]
+    None,
+    /// Picks a new shade every tick and darkens with age, like flames.
+    Flicker,
+    /// Pulses slowly and brightens the hotter the particle is, like lava.
+    Glow,
+}
+
+/// A particle touching a particle of element `with` reacts with the given probability,
+/// turning itself into `becomes` and the neighbour into `other_becomes`.
+/// `None` leaves that side unchanged.
+#[derive(Debug, Clone, Copy)]
+pub struct Reaction {
+    pub with: Element,
+    pub probability: f64,
+    pub becomes: Option<Element>,
+    pub other_becomes: Option<Element>,
+}
+
+/// Each tick there is a 1 in `chance` odds of placing `element` at `offset` from the particle.
+#[derive(Debug, Clone, Copy)]
+pub struct Emission {
+    pub element: Element,
+    pub chance: u32,
+    pub offset: (i32, i32),
+    /// Replace whatever is at the target cell, rather than only filling Air.
+    pub overwrite: bool,
+}
+
+/// The particle turns into `becomes` once its temperature crosses `at`.
+#[derive(Debug, Clone, Copy)]
+pub struct PhaseChange {
+    pub at: f32,
+    pub becomes: Element,
+}
+
+/// How a flammable element catches fire and burns.
+#[derive(Debug, Clone, Copy)]
+pub struct Combustion {
+    /// Odds per tick of catching fire from each burning or igniting neighbour.
+    pub chance: f64,
+    /// Catches fire on its own above this temperature.
+    pub ignition_temperature: Option<f32>,
+    /// Ticks it burns for before turning into its residue.
+    pub duration: u32,
+    /// What is left once it has burnt out, Air if `None`.
+    pub residue: Option<Element>,
+    /// The particle is held at least this hot while it burns.
+    pub temperature: f32,
+    /// Thrown into a random neighbouring Air cell with odds `flame_chance` per tick.
+    pub flame: Option<Element>,
+    pub flame_chance: f64,
+    /// Released into the Air cell above with odds `smoke_chance` per tick.
+    pub smoke: Option<Element>,
+    pub smoke_chance: f64,
+}
+
+/// Everything the simulation and renderer need to know about an element.
+#[derive(Debug, Clone)]
+pub struct ElementDef {
+    pub name: String,
+    /// The element's colour in palettes and PNG files.
+    pub color: Color,
+    /// Colours particles are drawn in, picked by their seed. Empty draws `color`.
+    pub shades: Vec<Color>,
+    /// How far a particle's brightness may differ from its shade, 0 to 255.
+    pub noise: u8,
+    pub shading: Shading,
+    /// Gives off light, drawn as a glow around the particle when bloom is on.
+    pub emissive: bool,
+    /// Blocks light from emissive particles.
+    pub opaque: bool,
+    pub density: i32,
+    /// Ticks before the particle turns into Air, 0 lives forever.
+    pub lifetime: u32,
+    pub is_static: bool,
+    pub movement: Movement,
+    /// Reactions where this element is the `a` side, in file order.
+    pub reactions: Vec<Reaction>,
+    pub emits: Vec<Emission>,
+    /// Temperature of newly spawned particles.
+    pub temperature: f32,
+    /// How much heat it takes to change the temperature, at least 1.
+    pub heat_capacity: f32,
+    /// How readily heat flows to and from neighbours, 0 to 1.
+    pub conductivity: f32,
+    /// Heat sources and sinks stay at this temperature no matter what touches them.
+    pub fixed_temperature: Option<f32>,
+    /// Fraction of the difference to the world's ambient temperature lost each tick.
+    pub cooling: f32,
+    /// Melting or boiling: taken when the temperature rises above `at`.
+    pub heats_into: Option<PhaseChange>,
+    /// Freezing or condensing: taken when the temperature falls below `at`.
+    pub cools_into: Option<PhaseChange>,
+    /// Sets flammable neighbours alight, like Fire and Lava do.
+    pub ignites: bool,
+    /// Flammable elements catch fire and burn.
+    pub burns: Option<Combustion>,
+}
+
+/// The set of elements a world is made of, usually loaded from `assets/elements.ron`.
+#[derive(Debug, Clone)]
+pub struct ElementRegistry {
+    defs: Vec<ElementDef>,
+    by_name: HashMap<String, Element>,
+}
+
+impl ElementRegistry {
+    /// The element definitions shipped in `assets/elements.ron`, compiled into the library.
+    pub fn builtin() -> ElementRegistry {
+        ElementRegistry::from_ron(include_str!("../assets/elements.ron"))
+            .expect("builtin element definitions are invalid")
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ElementRegistry, String> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        ElementRegistry::from_ron(&source)
+            .map_err(|e| format!("Failed to load {}: {}", path.display(), e))
+    }
+
+    pub fn from_ron(source: &str) -> Result<ElementRegistry, String> {
+        // implicit_some lets optional fields be written as `a_becomes: "Steam"`
+        let file: ElementFile = ron::Options::default()
+            .with_default_extension(ron::extensions::Extensions::IMPLICIT_SOME)
+            .from_str(source)
+            .map_err(|e| e.to_string())?;
+        ElementRegistry::from_file(file)
+    }
+
+    fn from_file(file: ElementFile) -> Result<ElementRegistry, String> {
+        if file.elements.len() > u16::MAX as usize {
+            return Err(format!("too many elements ({})", file.elements.len()));
+        }
+        match file.elements.first() {
+            Some(air) if air.name == "Air" => {}
+            _ => return Err("the first element must be Air".to_string()),
+        }
+
+        let mut by_name = HashMap::new();
+        for (id, raw) in file.elements.iter().enumerate() {
+            if by_name
+                .insert(raw.name.clone(), Element(id as u16))
+                .is_some()
+            {
+                return Err(format!("element {} is defined twice", raw.name));
+            }
         }
+        let lookup = |owner: &str, name: &str| {
+            by_name
+                .get(name)
+                .copied()
+                .ok_or_else(|| format!("{} refers to unknown element {}", owner, name))
+        };
+
+        let mut defs = Vec::with_capacity(file.elements.len());
+        for raw in &file.elements {
+            let emits = raw
+                .emits
+                .iter()
+                .map(|e| {
+                    if e.chance == 0 {
+                        return Err(format!("{} emits with a chance of 0", raw.name));
+                    }
+                    // chunks are updated in parallel on the assumption that nothing
+                    // reaches further than the next cell over
+                    if e.offset.0.abs() > 1 || e.offset.1.abs() > 1 {
+                        return Err(format!("{} emits further than one cell away", raw.name));
+                    }
+                    Ok(Emission {
+                        element: lookup(&raw.name, &e.element)?,
+                        chance: e.chance,
+                        offset: e.offset,
+                        overwrite: e.overwrite,
+                    })
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            if raw.heat_capacity < 1.0 {
+                return Err(format!("{} has a heat_capacity below 1", raw.name));
+            }
+            if !(0.0..=1.0).contains(&raw.conductivity) || !(0.0..=1.0).contains(&raw.cooling) {
+                return Err(format!(
+                    "{} has conductivity or cooling outside 0..=1",
+                    raw.name
+                ));
+            }
+            let phase_change = |change: &Option<RawPhaseChange>| -> Result<_, String> {
+                match change {
+                    Some(change) => Ok(Some(PhaseChange {
+                        at: change.at,
+                        becomes: lookup(&raw.name, &change.becomes)?,
+                    })),
+                    None => Ok(None),
+                }
+            };
+            let burns = match &raw.burns {
+                Some(burns) => {
+                    let chances = [burns.chance, burns.flame_chance, burns.smoke_chance];
+                    if chances.iter().any(|c| !(0.0..=1.0).contains(c)) {
+                        return Err(format!("{} burns with a chance outside 0..=1", raw.name));
+                    }
+                    if burns.duration == 0 {
+                        return Err(format!("{} burns for 0 ticks", raw.name));
+                    }
+                    let optional = |name: &Option<String>| match name {
+                        Some(name) => lookup(&raw.name, name).map(Some),
+                        None => Ok(None),
+                    };
+                    Some(Combustion {
+                        chance: burns.chance,
+                        ignition_temperature: burns.ignition_temperature,
+                        duration: burns.duration,
+                        residue: optional(&burns.residue)?,
+                        temperature: burns.temperature,
+                        flame: optional(&burns.flame)?,
+                        flame_chance: burns.flame_chance,
+                        smoke: optional(&burns.smoke)?,
+                        smoke_chance: burns.smoke_chance,
+                    })
+                }
+                None => None,
+            };
+            let (r, g, b, a) = raw.color;
+            defs.push(ElementDef {
+                name: raw.name.clone(),
+                color: Color::rgba(r, g, b, a),
+                shades: raw
+                    .shades
+                    .iter()
+                    .map(|&(r, g, b, a)| Color::rgba(r, g, b, a))
+                    .collect(),
+                noise: raw.noise,
+                shading: raw.shading,
+                emissive: raw.emissive,
+                opaque: raw.opaque,
+                density: raw.density,
+                lifetime: raw.lifetime,
+                is_static: raw.is_static,
+                movement: raw.movement,
+                reactions: Vec::new(),
+                emits,
+                temperature: raw.temperature,
+                heat_capacity: raw.heat_capacity,
+                conductivity: raw.conductivity,
+                fixed_temperature: raw.fixed_temperature,
+                cooling: raw.cooling,
+                heats_into: phase_change(&raw.heats_into)?,
+                cools_into: phase_change(&raw.cools_into)?,
+                ignites: raw.ignites,
+                burns,
+            });
+        }
+
+        for raw in &file.reactions {
+            let owner = format!("reaction {} + {}", raw.a, raw.b);
+            if !(0.0..=1.0).contains(&raw.probability) {
+                return Err(format!("{} has a probability outside 0..=1", owner));
+            }
+            let optional = |name: &Option<String>| match name {
+                Some(name) => lookup(&owner, name).map(Some),
+                None => Ok(None),
+            };
+            let a = lookup(&owner, &raw.a)?;
+            defs[a.0 as usize].reactions.push(Reaction {
+                with: lookup(&owner, &raw.b)?,
+                probability: raw.probability,
+                becomes: optional(&raw.a_becomes)?,
+                other_becomes: optional(&raw.b_becomes)?,
+            });
+        }
+
+        // PNG import tells elements apart by colour alone
+        Palette::check_distinct(defs.iter().map(|def| (def.name.as_str(), def.color)))?;
+
+        Ok(ElementRegistry { defs, by_name })
+    }
+
+    /// A fresh particle of `element` for the cell (x, y), at the element's spawn
+    /// temperature.
+    pub fn spawn(&self, element: Element, last_ticked: u32, x: i32, y: i32) -> Particle {
+        Particle {
+            temperature: self.get(element).temperature,
+            seed: color_seed(x, y, last_ticked),
+            ..Particle::new(element, last_ticked)
+        }
+    }
+
+    pub fn get(&self, element: Element) -> &ElementDef {
+        &self.defs[element.0 as usize]
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<Element> {
+        self.by_name.get(name).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.defs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.defs.is_empty()
+    }
+
+    pub fn elements(&self) -> impl Iterator<Item = Element> {
+        (0..self.defs.len() as u16).map(Element)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Element, &ElementDef)> {
+        self.elements().zip(self.defs.iter())
     }
 }
 
-impl Element {
-    pub fn color(&self) -> Color {
-        match *self {
-            Element::Air => Color::RGBA(0, 0, 0, 0),
-            Element::Sand => Color::RGBA(255, 255, 0, 255),
-            Element::Oil => Color::RGBA(255, 0, 255, 255),
-            Element::Water => Color::RGBA(0, 0, 255, 255),
-            Element::Fire => Color::RGBA(255, 0, 0, 255),
-            Element::Smoke => Color::RGBA(128, 128, 128, 255),
-            Element::Steam => Color::RGBA(200, 200, 255, 255),
-            Element::Wood => Color::RGBA(128, 64, 0, 255),
-            Element::Wall => Color::RGBA(255, 255, 255, 255),
-            Element::Ice => Color::RGBA(200, 200, 255, 255),
-            Element::Lava => Color::RGBA(255, 128, 0, 255),
+// on-disk layout of the element file, before names are resolved to ids
+
+#[derive(Deserialize)]
+struct ElementFile {
+    elements: Vec<RawElement>,
+    #[serde(default)]
+    reactions: Vec<RawReaction>,
+}
+
+#[derive(Deserialize)]
+struct RawElement {
+    name: String,
+    color: (u8, u8, u8, u8),
+    #[serde(default)]
+    shades: Vec<(u8, u8, u8, u8)>,
+    #[serde(default)]
+    noise: u8,
+    #[serde(default)]
+    shading: Shading,
+    #[serde(default)]
+    emissive: bool,
+    #[serde(default)]
+    opaque: bool,
+    #[serde(default)]
+    density: i32,
+    #[serde(default)]
+    lifetime: u32,
+    #[serde(default)]
+    is_static: bool,
+    #[serde(default)]
+    movement: Movement,
+    #[serde(default)]
+    emits: Vec<RawEmission>,
+    #[serde(default = "room_temperature")]
+    temperature: f32,
+    #[serde(default = "one")]
+    heat_capacity: f32,
+    #[serde(default = "half")]
+    conductivity: f32,
+    #[serde(default)]
+    fixed_temperature: Option<f32>,
+    #[serde(default)]
+    cooling: f32,
+    #[serde(default)]
+    heats_into: Option<RawPhaseChange>,
+    #[serde(default)]
+    cools_into: Option<RawPhaseChange>,
+    #[serde(default)]
+    ignites: bool,
+    #[serde(default)]
+    burns: Option<RawCombustion>,
+}
+
+#[derive(Deserialize)]
+struct RawCombustion {
+    chance: f64,
+    #[serde(default)]
+    ignition_temperature: Option<f32>,
+    duration: u32,
+    #[serde(default)]
+    residue: Option<String>,
+    #[serde(default = "burning_temperature")]
+    temperature: f32,
+    #[serde(default)]
+    flame: Option<String>,
+    #[serde(default)]
+    flame_chance: f64,
+    #[serde(default)]
+    smoke: Option<String>,
+    #[serde(default)]
+    smoke_chance: f64,
+}
+
+#[derive(Deserialize)]
+struct RawPhaseChange {
+    at: f32,
+    becomes: String,
+}
+
+#[derive(Deserialize)]
+struct RawReaction {
+    a: String,
+    b: String,
+    #[serde(default = "always")]
+    probability: f64,
+    #[serde(default)]
+    a_becomes: Option<String>,
+    #[serde(default)]
+    b_becomes: Option<String>,
+}
+
+fn always() -> f64 {
+    1.0
+}
+
+fn room_temperature() -> f32 {
+    ROOM_TEMPERATURE
+}
+
+fn burning_temperature() -> f32 {
+    600.0
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+fn half() -> f32 {
+    0.5
+}
+
+#[derive(Deserialize)]
+struct RawEmission {
+    element: String,
+    chance: u32,
+    #[serde(default)]
+    offset: (i32, i32),
+    #[serde(default)]
+    overwrite: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A registry of Air, Sand and Water followed by `extra` elements, with `reactions`.
+    fn registry(extra: &str, reactions: &str) -> Result<ElementRegistry, String> {
+        ElementRegistry::from_ron(&format!(
+            r#"(
+                elements: [
+                    (name: "Air", color: (0, 0, 0, 0)),
+                    (name: "Sand", color: (255, 255, 0, 255), movement: Powder),
+                    (name: "Water", color: (0, 0, 255, 255), movement: Liquid),
+                    {}
+                ],
+                reactions: [{}],
+            )"#,
+            extra, reactions
+        ))
+    }
+
+    #[test]
+    fn builtin_elements_load() {
+        let elements = ElementRegistry::builtin();
+        assert_eq!(elements.by_name("Air"), Some(Element::AIR));
+        for name in ["Sand", "Water", "Wood", "Fire", "Lava", "Steam"] {
+            assert!(elements.by_name(name).is_some(), "{} is missing", name);
+        }
+    }
+
+    #[test]
+    fn fields_are_read_and_defaulted() {
+        let elements = registry(
+            r#"(name: "Oil", color: (60, 40, 0, 255), movement: Liquid, density: 5,
+                temperature: 30.0, burns: (chance: 0.5, duration: 40, residue: "Sand"))"#,
+            "",
+        )
+        .unwrap();
+        let oil = elements.get(elements.by_name("Oil").unwrap());
+        assert_eq!(oil.movement, Movement::Liquid);
+        assert_eq!(oil.density, 5);
+        assert_eq!(oil.temperature, 30.0);
+        assert_eq!(oil.heat_capacity, 1.0);
+        let burns = oil.burns.unwrap();
+        assert_eq!(burns.duration, 40);
+        assert_eq!(burns.residue, elements.by_name("Sand"));
+        assert_eq!(burns.flame, None);
+    }
+
+    #[test]
+    fn first_element_must_be_air() {
+        let err =
+            ElementRegistry::from_ron(r#"(elements: [(name: "Sand", color: (255, 255, 0, 255))])"#)
+                .unwrap_err();
+        assert!(err.contains("must be Air"), "{}", err);
+    }
+
+    #[test]
+    fn invalid_definitions_are_rejected() {
+        let cases = [
+            (r#"(name: "Sand", color: (1, 2, 3, 255))"#, "defined twice"),
+            (
+                r#"(name: "Mud", color: (1, 2, 3, 255), cools_into: (at: 0.0, becomes: "Dirt"))"#,
+                "unknown element Dirt",
+            ),
+            (
+                r#"(name: "Mud", color: (1, 2, 3, 255), conductivity: 2.0)"#,
+                "outside 0..=1",
+            ),
+            (
+                r#"(name: "Mud", color: (1, 2, 3, 255), heat_capacity: 0.5)"#,
+                "below 1",
+            ),
+            (
+                r#"(name: "Mud", color: (1, 2, 3, 255), emits: [(element: "Sand", chance: 0)])"#,
+                "chance of 0",
+            ),
+            (
+                r#"(name: "Mud", color: (1, 2, 3, 255),
+                    emits: [(element: "Sand", chance: 5, offset: (0, 2))])"#,
+                "further than one cell",
+            ),
+            (
+                r#"(name: "Mud", color: (1, 2, 3, 255), burns: (chance: 0.5, duration: 0))"#,
+                "0 ticks",
+            ),
+            (
+                r#"(name: "Mud", color: (1, 2, 3, 255), movement: Fly)"#,
+                "Fly",
+            ),
+        ];
+        for (extra, expected) in cases {
+            let err = registry(extra, "").unwrap_err();
+            assert!(err.contains(expected), "{}: {}", extra, err);
+        }
+    }
+
+    #[test]
+    fn reactions_are_attached_to_their_first_element() {
+        let elements = registry(
+            "",
+            r#"(a: "Water", b: "Sand", probability: 0.25, b_becomes: "Water")"#,
+        )
+        .unwrap();
+        let (sand, water) = (elements.by_name("Sand"), elements.by_name("Water"));
+        assert!(elements.get(sand.unwrap()).reactions.is_empty());
+        let reactions = &elements.get(water.unwrap()).reactions;
+        assert_eq!(reactions.len(), 1);
+        assert_eq!(Some(reactions[0].with), sand);
+        assert_eq!(reactions[0].probability, 0.25);
+        assert_eq!(reactions[0].becomes, None);
+        assert_eq!(reactions[0].other_becomes, water);
+    }
+
+    #[test]
+    fn invalid_reactions_are_rejected() {
+        let cases = [
+            (
+                r#"(a: "Water", b: "Oil", a_becomes: "Sand")"#,
+                "unknown element Oil",
+            ),
+            (
+                r#"(a: "Water", b: "Sand", a_becomes: "Mud")"#,
+                "unknown element Mud",
+            ),
+            (
+                r#"(a: "Water", b: "Sand", probability: 1.5)"#,
+                "outside 0..=1",
+            ),
+        ];
+        for (reaction, expected) in cases {
+            let err = registry("", reaction).unwrap_err();
+            assert!(err.contains(expected), "{}: {}", reaction, err);
         }
     }
 }
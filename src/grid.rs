@@ -1,66 +1,196 @@
-use crate::{element::Element, particle::Particle};
+use crate::{
+    chunk::{ChunkMap, DirtyRect},
+    particle::Particle,
+};
 
+/// The world, stored as a single row-major buffer of cells.
+///
+/// `set`, `swap` and the bulk `_mut` views record what they touch in the chunk map so
+/// settled regions can sleep. Changes made through `get_mut` are not tracked; call
+/// `mark_dirty` after any that matter.
+#[derive(Clone)]
 pub struct Grid {
-    pub particles: Vec<Vec<Particle>>,
+    particles: Vec<Particle>,
+    width: i32,
+    height: i32,
+    chunks: ChunkMap,
+}
+
+impl Default for Grid {
+    fn default() -> Self {
+        Grid::new()
+    }
 }
 
 impl Grid {
-    // width and height
-    pub const WIDTH: i32 = 64;
-    pub const HEIGHT: i32 = 64;
-    // pub const WIDTH: i32 = 128;
-    // pub const HEIGHT: i32 = 128;
+    // default width and height, used by Grid::new
+    pub const DEFAULT_WIDTH: i32 = 64;
+    pub const DEFAULT_HEIGHT: i32 = 64;
+
     pub fn new() -> Grid {
+        Grid::with_size(Grid::DEFAULT_WIDTH, Grid::DEFAULT_HEIGHT)
+    }
+
+    /// Creates an empty grid of the given dimensions, filled with air.
+    pub fn with_size(width: i32, height: i32) -> Grid {
+        assert!(width > 0 && height > 0, "grid dimensions must be positive");
         Grid {
-            particles: vec![vec![Particle::default(); Grid::WIDTH as usize]; Grid::HEIGHT as usize],
+            particles: vec![Particle::default(); width as usize * height as usize],
+            width,
+            height,
+            chunks: ChunkMap::new(width, height),
         }
     }
 
-    // define an enumerated iterator over the grid with x and y
-    pub fn iter(&self) -> impl Iterator<Item = (i32, i32, &Particle)> {
-        (0..Grid::HEIGHT)
-            .flat_map(move |y| (0..Grid::WIDTH).map(move |x| (x, y)))
-            .map(move |(x, y)| (x, y, &self.particles[y as usize][x as usize]))
+    pub fn width(&self) -> i32 {
+        self.width
     }
 
-    pub fn get(&self, x: i32, y: i32) -> Option<&Particle> {
-        if Grid::pos_in_world(x, y) {
-            Some(&self.particles[y as usize][x as usize])
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    /// Index of (x, y) in the cell buffer. Does not bounds check.
+    pub fn index(&self, x: i32, y: i32) -> usize {
+        y as usize * self.width as usize + x as usize
+    }
+
+    /// Inverse of `index`.
+    pub fn pos_of(&self, index: usize) -> (i32, i32) {
+        (
+            (index % self.width as usize) as i32,
+            (index / self.width as usize) as i32,
+        )
+    }
+
+    /// Index of (x, y), or None if it is outside the world.
+    pub fn checked_index(&self, x: i32, y: i32) -> Option<usize> {
+        if self.pos_in_world(x, y) {
+            Some(self.index(x, y))
         } else {
             None
         }
     }
 
+    /// All cells, row-major.
+    pub fn cells(&self) -> &[Particle] {
+        &self.particles
+    }
+
+    /// Mutable view of every cell. Wakes the whole grid.
+    pub fn cells_mut(&mut self) -> &mut [Particle] {
+        self.chunks.wake_all();
+        &mut self.particles
+    }
+
+    pub fn row(&self, y: i32) -> &[Particle] {
+        let start = self.index(0, y);
+        &self.particles[start..start + self.width as usize]
+    }
+
+    /// Mutable view of row y. Wakes the row.
+    pub fn row_mut(&mut self, y: i32) -> &mut [Particle] {
+        self.chunks.mark_rect(DirtyRect {
+            x0: 0,
+            y0: y,
+            x1: self.width - 1,
+            y1: y,
+        });
+        let start = self.index(0, y);
+        let width = self.width as usize;
+        &mut self.particles[start..start + width]
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[Particle]> {
+        self.particles.chunks_exact(self.width as usize)
+    }
+
+    // define an enumerated iterator over the grid with x and y
+    pub fn iter(&self) -> impl Iterator<Item = (i32, i32, &Particle)> {
+        self.particles.iter().enumerate().map(move |(i, p)| {
+            let (x, y) = self.pos_of(i);
+            (x, y, p)
+        })
+    }
+
+    pub fn get(&self, x: i32, y: i32) -> Option<&Particle> {
+        self.checked_index(x, y).map(|i| &self.particles[i])
+    }
+
     pub fn get_mut(&mut self, x: i32, y: i32) -> Option<&mut Particle> {
-        if Grid::pos_in_world(x, y) {
-            Some(&mut self.particles[y as usize][x as usize])
-        } else {
-            None
-        }
+        self.checked_index(x, y)
+            .map(move |i| &mut self.particles[i])
     }
 
     pub fn set(&mut self, x: i32, y: i32, p: Particle) {
-        if !Grid::pos_in_world(x, y) {
-            return;
+        if let Some(i) = self.checked_index(x, y) {
+            self.particles[i] = p;
+            self.chunks.mark_dirty(x, y);
+        }
+    }
+
+    pub fn chunks(&self) -> &ChunkMap {
+        &self.chunks
+    }
+
+    /// Records a change to (x, y) made through `get_mut`, keeping its chunk awake.
+    pub fn mark_dirty(&mut self, x: i32, y: i32) {
+        self.chunks.mark_dirty(x, y);
+    }
+
+    /// Wakes every chunk, for after edits the chunk map could not see.
+    pub fn wake_all(&mut self) {
+        self.chunks.wake_all();
+    }
+
+    /// Starts a simulation step, see `ChunkMap::begin_step`.
+    pub fn begin_step(&mut self) {
+        self.chunks.begin_step();
+    }
+
+    /// Copies the cells in `rect` out into a grid of their own, with nothing marked dirty.
+    pub fn extract(&self, rect: DirtyRect) -> Grid {
+        let (width, height) = (rect.width(), rect.height());
+        let mut particles = Vec::with_capacity(width as usize * height as usize);
+        for y in rect.y0..=rect.y1 {
+            let start = self.index(rect.x0, y);
+            particles.extend_from_slice(&self.particles[start..start + width as usize]);
+        }
+        Grid {
+            particles,
+            width,
+            height,
+            chunks: ChunkMap::asleep(width, height),
+        }
+    }
+
+    /// Copies `part` back in with its top left corner at (x0, y0), along with the changes
+    /// it recorded. Those are grown by a cell, since `part` could not mark past its edges.
+    pub fn paste(&mut self, part: &Grid, x0: i32, y0: i32) {
+        for y in 0..part.height {
+            let start = self.index(x0, y0 + y);
+            self.particles[start..start + part.width as usize].copy_from_slice(part.row(y));
+        }
+        for rect in part.chunks.dirty_rects() {
+            self.chunks.mark_rect(rect.offset(x0, y0).grow(1));
         }
-        self.particles[y as usize][x as usize] = p;
     }
 
-    pub fn pos_in_world(x: i32, y: i32) -> bool {
-        x >= 0 && x < Grid::WIDTH && y >= 0 && y < Grid::HEIGHT
+    pub fn pos_in_world(&self, x: i32, y: i32) -> bool {
+        (0..self.width).contains(&x) && (0..self.height).contains(&y)
     }
 
     /// this is where you would implement bouyancy, for solids vs liquids, and liquids vs liquids
     pub fn swap(&mut self, x: i32, y: i32, new_x: i32, new_y: i32) -> bool {
-        if !Grid::pos_in_world(new_x, new_y) || !Grid::pos_in_world(x, y) {
-            return false;
+        match (self.checked_index(x, y), self.checked_index(new_x, new_y)) {
+            (Some(a), Some(b)) => {
+                self.particles.swap(a, b);
+                self.chunks.mark_dirty(x, y);
+                self.chunks.mark_dirty(new_x, new_y);
+                true
+            }
+            _ => false,
         }
-
-        let temp = self.particles[new_y as usize][new_x as usize].clone();
-        self.particles[new_y as usize][new_x as usize] =
-            self.particles[y as usize][x as usize].clone();
-        self.particles[y as usize][x as usize] = temp;
-        true
     }
 
     // pub fn swap(&mut self, x: i32, y: i32, new_x: i32, new_y: i32) -> bool {
@@ -0,0 +1,174 @@
+/*
+    Heat moves between the four direct neighbours of every cell. The flow across each edge
+    is proportional to the temperature difference and to the poorer conductor of the pair,
+    and a cell's change in temperature is that flow divided by its heat capacity, so heat
+    is conserved apart from heat sources and cooling towards the ambient temperature.
+
+    All cells read the temperatures from the start of the pass, which keeps the result
+    independent of the order cells are visited in. Only the awake parts of the grid are
+    updated, and a cell whose temperature is still moving keeps its chunk awake.
+*/
+
+use rayon::prelude::*;
+
+use crate::{chunk::DirtyRect, element::ElementRegistry, grid::Grid};
+
+/// Scales conductivity to the flow across one edge per tick. Together with heat
+/// capacities of at least 1 this keeps the update stable.
+const CONDUCTION_RATE: f32 = 0.2;
+
+const NEIGHBOURS: [(i32, i32); 4] = [(0, -1), (-1, 0), (1, 0), (0, 1)];
+
+/// Temperature changes smaller than this don't keep a chunk awake.
+const SETTLED: f32 = 0.01;
+
+/// Runs one tick of heat conduction over the awake parts of the grid, a chunk per task.
+pub fn conduct_heat(grid: &mut Grid, elements: &ElementRegistry, ambient_temperature: f32) {
+    let chunks = grid.chunks();
+    let rects: Vec<DirtyRect> = (0..chunks.chunks_y())
+        .flat_map(|cy| (0..chunks.chunks_x()).map(move |cx| (cx, cy)))
+        .filter_map(|(cx, cy)| chunks.active_rect(cx, cy))
+        .collect();
+
+    let updated: Vec<Vec<(i32, i32, f32)>> = rects
+        .par_iter()
+        .map(|rect| conduct_rect(grid, elements, ambient_temperature, *rect))
+        .collect();
+
+    for (x, y, t) in updated.into_iter().flatten() {
+        if let Some(p) = grid.get_mut(x, y) {
+            let settled = (p.temperature - t).abs() < SETTLED;
+            p.temperature = t;
+            if !settled {
+                grid.mark_dirty(x, y);
+            }
+        }
+    }
+}
+
+/// The new temperature of every cell in `rect`, without writing anything.
+fn conduct_rect(
+    grid: &Grid,
+    elements: &ElementRegistry,
+    ambient_temperature: f32,
+    rect: DirtyRect,
+) -> Vec<(i32, i32, f32)> {
+    let mut updated = Vec::with_capacity((rect.width() * rect.height()) as usize);
+    for y in rect.y0..=rect.y1 {
+        for x in rect.x0..=rect.x1 {
+            let p = grid.cells()[grid.index(x, y)];
+            let def = elements.get(p.element);
+            if let Some(fixed) = def.fixed_temperature {
+                updated.push((x, y, fixed));
+                continue;
+            }
+
+            let mut flow = 0.0;
+            for (dx, dy) in NEIGHBOURS.iter() {
+                if let Some(n) = grid.get(x + dx, y + dy) {
+                    let other = elements.get(n.element);
+                    let k = CONDUCTION_RATE * def.conductivity.min(other.conductivity);
+                    flow += k * (n.temperature - p.temperature);
+                }
+            }
+            let t = p.temperature + flow / def.heat_capacity;
+            updated.push((x, y, t + (ambient_temperature - t) * def.cooling));
+        }
+    }
+    updated
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        element::ElementRegistry,
+        world::{tests::world_with, World},
+    };
+
+    /// Air that doesn't conduct, Metal that does, and a Heater held at 500 degrees.
+    fn metals() -> ElementRegistry {
+        ElementRegistry::from_ron(
+            r#"(elements: [
+                (name: "Air", color: (0, 0, 0, 0), conductivity: 0.0),
+                (name: "Metal", color: (160, 160, 160, 255), heat_capacity: 2.0, conductivity: 1.0),
+                (name: "Heater", color: (255, 0, 0, 255), temperature: 500.0,
+                 fixed_temperature: 500.0, conductivity: 1.0),
+            ])"#,
+        )
+        .unwrap()
+    }
+
+    /// A world of `elements` with the given particles placed at the given temperatures.
+    fn heated(elements: ElementRegistry, cells: &[(i32, i32, &str, f32)]) -> World {
+        let placed: Vec<_> = cells.iter().map(|&(x, y, name, _)| (x, y, name)).collect();
+        let mut world = world_with(elements, &placed);
+        for &(x, y, _, temperature) in cells {
+            world.grid.get_mut(x, y).unwrap().temperature = temperature;
+        }
+        world
+    }
+
+    fn temperature_at(world: &World, x: i32, y: i32) -> f32 {
+        world.grid.get(x, y).unwrap().temperature
+    }
+
+    #[test]
+    fn heat_flows_from_hot_to_cold_and_is_conserved() {
+        let mut world = heated(metals(), &[(4, 4, "Metal", 100.0), (5, 4, "Metal", 0.0)]);
+        world.step();
+        let (hot, cold) = (temperature_at(&world, 4, 4), temperature_at(&world, 5, 4));
+        assert!(hot < 100.0 && cold > 0.0);
+        for _ in 0..100 {
+            world.step();
+        }
+        let (hot, cold) = (temperature_at(&world, 4, 4), temperature_at(&world, 5, 4));
+        assert!((hot - 50.0).abs() < 0.5 && (cold - 50.0).abs() < 0.5);
+        assert!((hot + cold - 100.0).abs() < 1e-3);
+        // Air doesn't conduct, so nothing else warmed up
+        assert_eq!(temperature_at(&world, 6, 4), 20.0);
+    }
+
+    #[test]
+    fn fixed_temperature_holds_and_heats_neighbours() {
+        let mut world = heated(metals(), &[(4, 4, "Heater", 500.0), (5, 4, "Metal", 20.0)]);
+        for _ in 0..200 {
+            world.step();
+        }
+        assert_eq!(temperature_at(&world, 4, 4), 500.0);
+        assert!(temperature_at(&world, 5, 4) > 490.0);
+    }
+
+    #[test]
+    fn phase_changes_follow_temperature() {
+        let mut world = heated(
+            ElementRegistry::builtin(),
+            &[
+                (2, 2, "Water", 150.0),
+                (6, 2, "Water", -10.0),
+                (10, 2, "Steam", 50.0),
+                (14, 2, "Ice", 30.0),
+                (2, 10, "Water", 50.0),
+            ],
+        );
+        world.step();
+        let count = |name| {
+            let element = world.elements.by_name(name);
+            world
+                .grid
+                .cells()
+                .iter()
+                .filter(|p| Some(p.element) == element)
+                .count()
+        };
+        assert_eq!((count("Steam"), count("Ice"), count("Water")), (1, 1, 3));
+        // the particle keeps its temperature through the change
+        let steam = world.elements.by_name("Steam").unwrap();
+        let steam = world
+            .grid
+            .cells()
+            .iter()
+            .find(|p| p.element == steam)
+            .unwrap();
+        assert!(steam.temperature > 100.0);
+    }
+}
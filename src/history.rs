@@ -0,0 +1,216 @@
+/*
+    Undo history for edits made through `World::apply`. Each entry holds only the cells an
+    edit changed, with what they held just before it. Undoing swaps those particles back
+    into the grid and keeps what the cells held instead as the redo entry, so the rest of
+    the world carries on as it was: only the edited cells go back, whatever the simulation
+    has done with them since.
+
+    Consecutive edits go into one entry until it is closed, so a whole brush drag undoes
+    in one go. Undo and redo entries share the budget, and the oldest entries are dropped
+    once they grow past it. An edit bigger than the whole budget, like clearing a huge
+    world, can't be undone, and leaves the history empty.
+*/
+
+use std::collections::{HashSet, VecDeque};
+use std::mem::size_of;
+
+use crate::{grid::Grid, particle::Particle};
+
+/// A changed cell: its index, and the particle it held before the change.
+type CellChange = (usize, Particle);
+
+#[derive(Clone, Default)]
+struct Entry {
+    changes: Vec<CellChange>,
+}
+
+impl Entry {
+    fn bytes(&self) -> usize {
+        self.changes.len() * size_of::<CellChange>()
+    }
+
+    /// Writes the stored particles back into `grid`, returning an entry with what the
+    /// cells held instead.
+    fn swap(self, grid: &mut Grid) -> Entry {
+        let mut changes = self.changes;
+        for (index, p) in changes.iter_mut().rev() {
+            let (x, y) = grid.pos_of(*index);
+            if let Some(cell) = grid.get(x, y) {
+                let current = *cell;
+                grid.set(x, y, *p);
+                *p = current;
+            }
+        }
+        Entry { changes }
+    }
+}
+
+#[derive(Clone)]
+pub struct History {
+    undo: VecDeque<Entry>,
+    redo: VecDeque<Entry>,
+    /// Cells already in the newest undo entry, while it still takes more changes.
+    open: Option<HashSet<usize>>,
+    /// Whether the edit in progress outgrew the budget and is no longer recorded.
+    overflowed: bool,
+    budget: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History::with_budget(History::DEFAULT_BUDGET)
+    }
+}
+
+impl History {
+    /// Bytes of changed cells kept by default.
+    pub const DEFAULT_BUDGET: usize = 64 * 1024 * 1024;
+
+    /// An empty history that keeps about `budget` bytes of changed cells.
+    pub fn with_budget(budget: usize) -> History {
+        History {
+            undo: VecDeque::new(),
+            redo: VecDeque::new(),
+            open: None,
+            overflowed: false,
+            budget,
+        }
+    }
+
+    /// Call before an edit changes the cell at `index` of `grid`. Keeps what the cell
+    /// holds, unless the open entry already has it, so the edit undoes back to here.
+    pub fn before_change(&mut self, grid: &Grid, index: usize) {
+        if self.overflowed {
+            return;
+        }
+        let seen = match &mut self.open {
+            Some(seen) => seen,
+            None => {
+                self.undo.push_back(Entry::default());
+                self.redo.clear();
+                self.open.insert(HashSet::new())
+            }
+        };
+        if !seen.insert(index) {
+            return;
+        }
+        let entry = self.undo.back_mut().unwrap();
+        entry.changes.push((index, grid.cells()[index]));
+        if entry.bytes() > self.budget {
+            // too big to ever keep; forget it and everything before it, which it overwrote
+            self.clear();
+            self.overflowed = true;
+        }
+    }
+
+    /// Closes the open entry, so the next change starts a new one, and trims the history
+    /// to its budget.
+    pub fn end_edit(&mut self) {
+        self.open = None;
+        self.overflowed = false;
+        let mut total: usize = self.undo.iter().chain(&self.redo).map(Entry::bytes).sum();
+        while total > self.budget && !self.undo.is_empty() {
+            total -= self.undo.pop_front().unwrap().bytes();
+        }
+        while total > self.budget && !self.redo.is_empty() {
+            total -= self.redo.pop_front().unwrap().bytes();
+        }
+    }
+
+    /// Puts the cells of the newest entry back to before it. Returns false if there was
+    /// nothing to undo.
+    pub fn undo(&mut self, grid: &mut Grid) -> bool {
+        self.end_edit();
+        let Some(entry) = self.undo.pop_back() else {
+            return false;
+        };
+        self.redo.push_back(entry.swap(grid));
+        true
+    }
+
+    /// Puts the cells of the newest undo back to before it. Returns false if there was
+    /// nothing to redo.
+    pub fn redo(&mut self, grid: &mut Grid) -> bool {
+        self.end_edit();
+        let Some(entry) = self.redo.pop_back() else {
+            return false;
+        };
+        self.undo.push_back(entry.swap(grid));
+        true
+    }
+
+    /// Forgets every entry.
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.open = None;
+        self.overflowed = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        action::Action,
+        brush::Brush,
+        element::ElementRegistry,
+        tools::Shape,
+        world::{
+            tests::{name_at, world_with},
+            World,
+        },
+    };
+
+    fn wall_row(world: &mut World, y: i32) {
+        let wall = world.elements.by_name("Wall").unwrap();
+        world.apply(&Action::Shape {
+            shape: Shape::Rect {
+                from: (0, y),
+                to: (7, y),
+                filled: true,
+            },
+            element: wall,
+            brush: Brush::default(),
+        });
+    }
+
+    #[test]
+    fn undo_only_reverts_the_edited_cells() {
+        let mut world = world_with(ElementRegistry::builtin(), &[(12, 0, "Sand")]);
+        wall_row(&mut world, 8);
+        for _ in 0..5 {
+            world.step();
+        }
+        world.apply(&Action::Undo);
+        assert_eq!(name_at(&world, 3, 8), "Air");
+        // the sand kept falling instead of going back to where it was before the edit
+        assert_eq!(name_at(&world, 12, 0), "Air");
+        world.apply(&Action::Redo);
+        assert_eq!(name_at(&world, 3, 8), "Wall");
+        // nothing left to redo
+        assert!(!world.history.redo(&mut world.grid));
+    }
+
+    #[test]
+    fn history_stays_within_its_budget() {
+        let mut world = world_with(ElementRegistry::builtin(), &[]);
+        let row = 8 * size_of::<CellChange>();
+        world.history = History::with_budget(2 * row);
+        for y in 0..3 {
+            wall_row(&mut world, y);
+        }
+        // the oldest row was dropped
+        world.apply(&Action::Undo);
+        world.apply(&Action::Undo);
+        assert!(!world.history.undo(&mut world.grid));
+        assert_eq!(name_at(&world, 0, 0), "Wall");
+        assert_eq!(name_at(&world, 0, 1), "Air");
+
+        // an edit bigger than the whole budget isn't kept at all
+        world.history = History::with_budget(row);
+        world.apply(&Action::Clear);
+        assert!(!world.history.undo(&mut world.grid));
+        assert_eq!(name_at(&world, 0, 0), "Air");
+    }
+}
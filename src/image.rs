@@ -0,0 +1,309 @@
+/*
+    PNG import and export. Each pixel is one cell: export writes every cell in its
+    palette colour, and import picks the element whose palette colour is nearest to each
+    pixel, so a level can be painted in any image editor without exact colours.
+
+    The default palette is the colours from the element file. A palette file overrides
+    some of them, which is useful when a level was painted with a different set of
+    colours:
+
+        {
+            "Ice": (150, 200, 255),
+            "Steam": (230, 230, 230),
+        }
+
+    No two elements may share a palette colour, in the element file or after overrides,
+    or their cells would come back from a PNG as the same element.
+
+    Pixels that are mostly transparent always import as Air. Images wider or taller than
+    MAX_WORLD_SIDE are refused, so that every imported grid can be saved and loaded again.
+*/
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+use crate::{
+    color::Color,
+    element::{Element, ElementRegistry},
+    grid::Grid,
+    save::{MAX_WORLD_CELLS, MAX_WORLD_SIDE},
+};
+
+/// The widest or tallest PNG `export_png` writes, enough for the biggest world at four
+/// pixels per cell.
+pub const MAX_EXPORT_SIDE: u32 = 4 * MAX_WORLD_SIDE;
+
+/// The colour each element is written as, and matched against, in a PNG.
+#[derive(Debug, Clone)]
+pub struct Palette {
+    colors: Vec<Color>,
+}
+
+impl Palette {
+    /// Every element in its own colour.
+    pub fn from_elements(elements: &ElementRegistry) -> Palette {
+        Palette {
+            colors: elements.iter().map(|(_, def)| def.color).collect(),
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P, elements: &ElementRegistry) -> Result<Palette, String> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Palette::from_ron(&source, elements)
+            .map_err(|e| format!("Failed to load {}: {}", path.display(), e))
+    }
+
+    /// The element colours, with those named in `source` replaced.
+    pub fn from_ron(source: &str, elements: &ElementRegistry) -> Result<Palette, String> {
+        let overrides: HashMap<String, (u8, u8, u8)> =
+            ron::from_str(source).map_err(|e| e.to_string())?;
+        let mut palette = Palette::from_elements(elements);
+        for (name, (r, g, b)) in overrides {
+            let element = elements
+                .by_name(&name)
+                .ok_or_else(|| format!("unknown element {}", name))?;
+            palette.colors[element.id() as usize] = Color::rgb(r, g, b);
+        }
+        Palette::check_distinct(
+            elements
+                .iter()
+                .map(|(element, def)| (def.name.as_str(), palette.color(element))),
+        )?;
+        Ok(palette)
+    }
+
+    /// Fails if two of the named colours are the same, ignoring alpha as `nearest` does.
+    pub fn check_distinct<'a>(
+        colors: impl IntoIterator<Item = (&'a str, Color)>,
+    ) -> Result<(), String> {
+        let mut seen: HashMap<(u8, u8, u8), &str> = HashMap::new();
+        for (name, c) in colors {
+            if let Some(other) = seen.insert((c.r, c.g, c.b), name) {
+                return Err(format!("{} has the same colour as {}", name, other));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn color(&self, element: Element) -> Color {
+        self.colors[element.id() as usize]
+    }
+
+    /// The element whose colour is closest to `c`. Ties go to the lower id.
+    pub fn nearest(&self, c: Color) -> Element {
+        if c.a < 128 {
+            return Element::AIR;
+        }
+        let distance = |p: &Color| {
+            let dr = p.r as i32 - c.r as i32;
+            let dg = p.g as i32 - c.g as i32;
+            let db = p.b as i32 - c.b as i32;
+            dr * dr + dg * dg + db * db
+        };
+        let id = (0..self.colors.len())
+            .min_by_key(|&id| distance(&self.colors[id]))
+            .unwrap_or(0);
+        Element::from_id(id as u16)
+    }
+
+    /// Fills `buf` with the grid as row-major RGBA bytes, one pixel per cell.
+    pub fn fill_rgba(&self, grid: &Grid, buf: &mut Vec<u8>) {
+        buf.clear();
+        buf.reserve(grid.cells().len() * 4);
+        for p in grid.cells() {
+            let c = self.color(p.element);
+            buf.extend_from_slice(&[c.r, c.g, c.b, c.a]);
+        }
+    }
+}
+
+impl Grid {
+    /// Writes the grid to a PNG with each cell drawn as a `scale` x `scale` block. Fails if
+    /// the image would be wider or taller than MAX_EXPORT_SIDE.
+    pub fn export_png<P: AsRef<Path>>(
+        &self,
+        path: P,
+        palette: &Palette,
+        scale: u32,
+    ) -> Result<(), String> {
+        let path = path.as_ref();
+        let scale = scale.max(1);
+        let side = |cells: i32| {
+            (cells as u32)
+                .checked_mul(scale)
+                .filter(|&s| s <= MAX_EXPORT_SIDE)
+        };
+        let (Some(width), Some(height)) = (side(self.width()), side(self.height())) else {
+            return Err(format!(
+                "{} at scale {} would be larger than {}x{} pixels",
+                path.display(),
+                scale,
+                MAX_EXPORT_SIDE,
+                MAX_EXPORT_SIDE
+            ));
+        };
+        let (width, height, scale) = (width as usize, height as usize, scale as usize);
+        let mut cells = Vec::new();
+        palette.fill_rgba(self, &mut cells);
+
+        let mut pixels = Vec::with_capacity(width * height * 4);
+        for row in cells.chunks_exact(self.width() as usize * 4) {
+            let start = pixels.len();
+            for pixel in row.chunks_exact(4) {
+                for _ in 0..scale {
+                    pixels.extend_from_slice(pixel);
+                }
+            }
+            for _ in 1..scale {
+                pixels.extend_from_within(start..start + width * 4);
+            }
+        }
+
+        let file = File::create(path)
+            .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), width as u32, height as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .and_then(|mut w| w.write_image_data(&pixels))
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    /// Reads a PNG as a grid with one cell per pixel, every chunk awake.
+    pub fn import_png<P: AsRef<Path>>(
+        path: P,
+        palette: &Palette,
+        elements: &ElementRegistry,
+    ) -> Result<Grid, String> {
+        let path = path.as_ref();
+        let file =
+            File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        let mut decoder = png::Decoder::new(BufReader::new(file));
+        // expand palettes and low bit depths so every format arrives as 8 bit gray or RGB(A)
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder
+            .read_info()
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        // refuse images too big to save as a world before allocating their pixels
+        let (width, height) = reader.info().size();
+        if width > MAX_WORLD_SIDE
+            || height > MAX_WORLD_SIDE
+            || width as u64 * height as u64 > MAX_WORLD_CELLS
+        {
+            return Err(format!(
+                "{} is {}x{}, larger than the biggest world of {}x{}",
+                path.display(),
+                width,
+                height,
+                MAX_WORLD_SIDE,
+                MAX_WORLD_SIDE
+            ));
+        }
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader
+            .next_frame(&mut pixels)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+        let to_color = |p: &[u8]| match info.color_type {
+            png::ColorType::Grayscale => Color::rgb(p[0], p[0], p[0]),
+            png::ColorType::GrayscaleAlpha => Color::rgba(p[0], p[0], p[0], p[1]),
+            png::ColorType::Rgb => Color::rgb(p[0], p[1], p[2]),
+            _ => Color::rgba(p[0], p[1], p[2], p[3]),
+        };
+        let channels = info.color_type.samples();
+
+        let mut grid = Grid::with_size(info.width as i32, info.height as i32);
+        let rows = pixels.chunks_exact(info.line_size);
+        let cells = rows.flat_map(|row| row.chunks_exact(channels).take(info.width as usize));
+        let width = info.width as usize;
+        for (i, (cell, pixel)) in grid.cells_mut().iter_mut().zip(cells).enumerate() {
+            let (x, y) = ((i % width) as i32, (i / width) as i32);
+            *cell = elements.spawn(palette.nearest(to_color(pixel)), 0, x, y);
+        }
+        Ok(grid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes a black grayscale PNG of the given size to the temporary directory.
+    fn gray_png(name: &str, width: u32, height: u32) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(name);
+        let file = BufWriter::new(File::create(&path).unwrap());
+        let mut encoder = png::Encoder::new(file, width, height);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        let pixels = vec![0; (width * height) as usize];
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&pixels).unwrap();
+        path
+    }
+
+    #[test]
+    fn import_refuses_images_larger_than_a_world() {
+        let elements = ElementRegistry::builtin();
+        let palette = Palette::from_elements(&elements);
+        let path = gray_png("fallingsand-too-wide.png", MAX_WORLD_SIDE + 1, 1);
+        assert!(Grid::import_png(&path, &palette, &elements).is_err());
+        let path = gray_png("fallingsand-widest.png", MAX_WORLD_SIDE, 1);
+        let grid = Grid::import_png(&path, &palette, &elements).unwrap();
+        assert_eq!((grid.width(), grid.height()), (MAX_WORLD_SIDE as i32, 1));
+    }
+
+    #[test]
+    fn export_then_import_gives_back_the_elements() {
+        let elements = ElementRegistry::builtin();
+        let palette = Palette::from_elements(&elements);
+        let mut grid = Grid::with_size(8, 8);
+        let ids: Vec<_> = elements.iter().map(|(element, _)| element).collect();
+        for (i, (x, y, _)) in Grid::with_size(8, 8).iter().enumerate() {
+            let element = ids[i % ids.len()];
+            grid.set(x, y, elements.spawn(element, 0, x, y));
+        }
+        let path = std::env::temp_dir().join("fallingsand-round-trip.png");
+        grid.export_png(&path, &palette, 1).unwrap();
+        let imported = Grid::import_png(&path, &palette, &elements).unwrap();
+        let element_ids = |g: &Grid| g.cells().iter().map(|p| p.element).collect::<Vec<_>>();
+        assert_eq!(element_ids(&imported), element_ids(&grid));
+    }
+
+    #[test]
+    fn export_refuses_scales_too_large_to_write() {
+        let elements = ElementRegistry::builtin();
+        let palette = Palette::from_elements(&elements);
+        let path = std::env::temp_dir().join("fallingsand-too-large.png");
+        let grid = Grid::with_size(8, 8);
+        assert!(grid.export_png(&path, &palette, 100_000).is_err());
+        assert!(grid.export_png(&path, &palette, u32::MAX).is_err());
+    }
+
+    #[test]
+    fn nearest_colour_and_overrides() {
+        let elements = ElementRegistry::builtin();
+        let sand = elements.by_name("Sand").unwrap();
+        let palette = Palette::from_elements(&elements);
+        let c = palette.color(sand);
+        let near = Color::rgb(c.r.saturating_sub(3), c.g.saturating_add(2), c.b);
+        assert_eq!(palette.nearest(near), sand);
+        assert_eq!(
+            palette.nearest(Color::rgba(c.r, c.g, c.b, 20)),
+            Element::AIR
+        );
+
+        let palette = Palette::from_ron(r#"{"Sand": (1, 2, 3)}"#, &elements).unwrap();
+        assert_eq!(palette.color(sand), Color::rgb(1, 2, 3));
+        assert_eq!(palette.nearest(Color::rgb(0, 3, 3)), sand);
+        assert!(Palette::from_ron(r#"{"Unobtainium": (1, 2, 3)}"#, &elements).is_err());
+        // two elements may not end up with the same colour
+        let water = palette.color(elements.by_name("Water").unwrap());
+        let clash = format!(r#"{{"Sand": ({}, {}, {})}}"#, water.r, water.g, water.b);
+        assert!(Palette::from_ron(&clash, &elements).is_err());
+    }
+}
@@ -0,0 +1,24 @@
+//! Headless falling sand simulation.
+//!
+//! Everything needed to build and step a world lives here, with no dependency on a
+//! display. The SDL2 front end in `main.rs` is built on top of it behind the `sdl` feature.
+
+pub mod action;
+pub mod bloom;
+pub mod brush;
+pub mod camera;
+pub mod chunk;
+pub mod color;
+pub mod element;
+pub mod grid;
+pub mod heat;
+pub mod history;
+pub mod image;
+pub mod lighting;
+pub mod particle;
+pub mod particle_behaviour;
+pub mod replay;
+pub mod save;
+pub mod shading;
+pub mod tools;
+pub mod world;
@@ -0,0 +1,174 @@
+/*
+    2D lighting for dark worlds. Emissive and burning particles are light sources in their
+    own colour.
+    Their light spreads to neighbouring cells, dimmed by the falloff at every step, until
+    it is too faint to see. Opaque particles like Wall are lit by light reaching them but
+    don't pass it on, so they cast shadows. Each cell is then drawn at the world's ambient
+    light level plus whatever light reaches it, per colour channel; the sources themselves
+    keep their full colour.
+
+    Light spreads breadth first from the sources, and a cell is revisited only when a
+    brighter path reaches it, so the cost stays close to the lit area. A fully lit world
+    (ambient light 1) skips all of it.
+*/
+
+use std::collections::VecDeque;
+
+use crate::world::World;
+
+/// Light below this isn't passed on; it wouldn't change a pixel.
+const MIN_LIGHT: f32 = 1.0 / 255.0;
+
+pub struct Lighting {
+    /// Fraction of the light kept at each step away from the source.
+    pub falloff: f32,
+    light: Vec<[f32; 3]>,
+    queue: VecDeque<usize>,
+}
+
+impl Lighting {
+    pub fn new(falloff: f32) -> Lighting {
+        Lighting {
+            falloff,
+            light: Vec::new(),
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Darkens `pixels`, which holds the world as drawn by `World::fill_rgba`, to the
+    /// world's ambient light, then lights it up around emissive and burning particles.
+    pub fn apply(&mut self, world: &World, pixels: &mut [u8]) {
+        let ambient = world.ambient_light;
+        if ambient >= 1.0 {
+            return;
+        }
+        let grid = &world.grid;
+        let elements = &world.elements;
+
+        self.light.clear();
+        self.light.resize(grid.cells().len(), [0.0; 3]);
+        self.queue.clear();
+        for (i, p) in grid.cells().iter().enumerate() {
+            if elements.get(p.element).gives_light(p) {
+                let c = &pixels[i * 4..i * 4 + 3];
+                self.light[i] = [c[0], c[1], c[2]].map(|c| c as f32 / 255.0);
+                self.queue.push_back(i);
+            }
+        }
+
+        while let Some(i) = self.queue.pop_front() {
+            let p = &grid.cells()[i];
+            let def = elements.get(p.element);
+            if def.opaque && !def.gives_light(p) {
+                continue;
+            }
+            let spread = self.light[i].map(|c| c * self.falloff);
+            if spread.iter().all(|&c| c < MIN_LIGHT) {
+                continue;
+            }
+            let (x, y) = grid.pos_of(i);
+            for (dx, dy) in [(0, -1), (-1, 0), (1, 0), (0, 1)] {
+                let (nx, ny) = (x + dx, y + dy);
+                if grid.get(nx, ny).is_none() {
+                    continue;
+                }
+                let n = grid.index(nx, ny);
+                let light = &mut self.light[n];
+                if spread.iter().zip(light.iter()).any(|(s, l)| s > l) {
+                    for (l, s) in light.iter_mut().zip(spread) {
+                        *l = l.max(s);
+                    }
+                    self.queue.push_back(n);
+                }
+            }
+        }
+
+        for (i, (pixel, light)) in pixels.chunks_exact_mut(4).zip(&self.light).enumerate() {
+            // light sources are drawn at full brightness, not dimmed by their own colour
+            let p = &grid.cells()[i];
+            if elements.get(p.element).gives_light(p) {
+                continue;
+            }
+            for (c, l) in pixel.iter_mut().zip(light) {
+                *c = (*c as f32 * (ambient + l).min(1.0)) as u8;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{element::ElementRegistry, world::tests::world_with};
+
+    /// The world drawn by `fill_rgba` and then lit.
+    fn lit(world: &World) -> Vec<u8> {
+        let mut pixels = Vec::new();
+        world.fill_rgba(&mut pixels);
+        Lighting::new(0.5).apply(world, &mut pixels);
+        pixels
+    }
+
+    fn red_at(world: &World, pixels: &[u8], x: i32, y: i32) -> u8 {
+        pixels[world.grid.index(x, y) * 4]
+    }
+
+    #[test]
+    fn burning_particles_give_off_light() {
+        let mut world = world_with(
+            ElementRegistry::builtin(),
+            &[(4, 4, "Wood"), (5, 4, "Sand"), (12, 12, "Sand")],
+        );
+        world.ambient_light = 0.0;
+        let dark = lit(&world);
+        assert_eq!(red_at(&world, &dark, 5, 4), 0);
+
+        world.grid.get_mut(4, 4).unwrap().burning = 10;
+        let pixels = lit(&world);
+        assert!(red_at(&world, &pixels, 5, 4) > 0);
+        assert!(red_at(&world, &pixels, 4, 4) > 0);
+        assert_eq!(red_at(&world, &pixels, 12, 12), 0);
+    }
+
+    /// Lava on the left of a wall that runs top to bottom, with Sand on either side.
+    fn walled_lava(ambient_light: f32) -> World {
+        let mut cells: Vec<_> = (0..16).map(|y| (6, y, "Wall")).collect();
+        cells.extend([(2, 4, "Lava"), (3, 4, "Sand"), (9, 4, "Sand")]);
+        let mut world = world_with(ElementRegistry::builtin(), &cells);
+        world.ambient_light = ambient_light;
+        world
+    }
+
+    fn unlit(world: &World) -> Vec<u8> {
+        let mut pixels = Vec::new();
+        world.fill_rgba(&mut pixels);
+        pixels
+    }
+
+    #[test]
+    fn opaque_cells_are_lit_but_cast_shadows() {
+        let world = walled_lava(0.2);
+        let (pixels, drawn) = (lit(&world), unlit(&world));
+        let dimmed = |x, y| (red_at(&world, &drawn, x, y) as f32 * 0.2) as u8;
+        assert!(red_at(&world, &pixels, 3, 4) > dimmed(3, 4));
+        assert!(red_at(&world, &pixels, 6, 4) > dimmed(6, 4));
+        // behind the wall it is as dark as the ambient light makes it
+        assert_eq!(red_at(&world, &pixels, 9, 4), dimmed(9, 4));
+    }
+
+    #[test]
+    fn light_sources_are_not_darkened() {
+        let world = walled_lava(0.0);
+        let (pixels, drawn) = (lit(&world), unlit(&world));
+        let i = world.grid.index(2, 4) * 4;
+        assert_eq!(pixels[i..i + 4], drawn[i..i + 4]);
+    }
+
+    #[test]
+    fn full_ambient_light_leaves_pixels_alone() {
+        for ambient in [1.0, 1.5] {
+            let world = walled_lava(ambient);
+            assert_eq!(lit(&world), unlit(&world));
+        }
+    }
+}
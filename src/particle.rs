@@ -1,82 +1,57 @@
-use sdl2::pixels::Color;
-
 use crate::element::Element;
 
+/// Temperature of empty space and of particles that don't say otherwise.
+pub const ROOM_TEMPERATURE: f32 = 20.0;
+
 #[derive(Clone, Copy)]
 pub struct Particle {
     pub element: Element,
     pub last_ticked: u32,
     pub age: u32,
+    pub temperature: f32,
+    /// Ticks left to burn, 0 when not on fire.
+    pub burning: u32,
+    /// Picks this particle's shade of its element's colour, see `ElementDef::shade`.
+    /// Only used for drawing.
+    pub seed: u8,
 }
 
 impl Default for Particle {
     fn default() -> Self {
         Particle {
-            element: Element::Air,
+            element: Element::AIR,
             last_ticked: 0,
             age: 0,
+            temperature: ROOM_TEMPERATURE,
+            burning: 0,
+            seed: 0,
         }
     }
 }
 
 impl Particle {
+    /// A particle at room temperature. Use `ElementRegistry::spawn` to start it at
+    /// its element's own temperature instead.
     pub fn new(element: Element, last_ticked: u32) -> Particle {
         Particle {
             element,
-            last_ticked: last_ticked,
+            last_ticked,
             age: 0,
+            temperature: ROOM_TEMPERATURE,
+            burning: 0,
+            seed: 0,
         }
     }
+}
 
-    pub fn color(&self) -> Color {
-        self.element.color()
-    }
-
-    pub fn lifetime(&self) -> u32 {
-        match self.element {
-            Element::Air => 0,
-            Element::Sand => 0,
-            Element::Water => 0,
-            Element::Oil => 0,
-            Element::Fire => 8,
-            Element::Smoke => 120,
-            Element::Steam => 0,
-            Element::Wood => 0,
-            Element::Wall => 0,
-            Element::Ice => 0,
-            Element::Lava => 0,
-        }
-    }
-
-    pub fn density(&self) -> i32 {
-        match self.element {
-            Element::Air => 0,
-            Element::Sand => 20,
-            Element::Water => 10,
-            Element::Oil => 9,
-            Element::Fire => 0,
-            Element::Smoke => -1,
-            Element::Steam => -2,
-            Element::Wood => 30,
-            Element::Wall => 100,
-            Element::Ice => 30,
-            Element::Lava => 30,
-        }
-    }
-
-    pub fn is_static(&self) -> bool {
-        match self.element {
-            Element::Air => false,
-            Element::Sand => false,
-            Element::Water => false,
-            Element::Oil => false,
-            Element::Fire => false,
-            Element::Smoke => false,
-            Element::Steam => false,
-            Element::Wood => false,
-            Element::Wall => true,
-            Element::Ice => false,
-            Element::Lava => false,
-        }
-    }
+/// A colour seed for a particle placed at (x, y) on tick `tick`. Hashed rather than drawn
+/// from the world's random number generator, so drawing never changes how a world plays out.
+pub fn color_seed(x: i32, y: i32, tick: u32) -> u8 {
+    let mut h = (x as u32).wrapping_mul(0x9e37_79b1)
+        ^ (y as u32).wrapping_mul(0x85eb_ca77)
+        ^ tick.wrapping_mul(0xc2b2_ae3d);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x2c1b_3c6d);
+    h ^= h >> 12;
+    (h >> 24) as u8
 }
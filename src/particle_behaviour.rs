@@ -6,14 +6,20 @@
 
     TODO:
         - try to add a high viscosity gel
-        - wood
     //NOTE: try the behaviour as struct for encapsulating grid and particle
     CONSIDER:
         -
 */
 
-use crate::{element::Element, grid::Grid, particle::Particle};
-use rand::Rng;
+use crate::{
+    chunk::DirtyRect,
+    element::{Element, ElementRegistry, Movement},
+    grid::Grid,
+    particle::Particle,
+    world::SimRng,
+};
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
 
 const DIRECTIONS: [(i32, i32); 8] = [
     (-1, -1),
@@ -26,8 +32,8 @@ const DIRECTIONS: [(i32, i32); 8] = [
     (1, 1),
 ];
 
-pub fn on_floor(y: i32) -> bool {
-    y == Grid::HEIGHT - 1
+pub fn on_floor(grid: &Grid, y: i32) -> bool {
+    y == grid.height() - 1
 }
 
 pub fn on_ceiling(y: i32) -> bool {
@@ -36,85 +42,113 @@ pub fn on_ceiling(y: i32) -> bool {
 
 pub fn set_if_empty(grid: &mut Grid, x: i32, y: i32, p: Particle) {
     if let Some(tp) = grid.get(x, y) {
-        if tp.element == Element::Air {
+        if tp.element == Element::AIR {
             grid.set(x, y, p);
         }
     }
 }
 
-pub fn try_move(grid: &mut Grid, x: i32, y: i32, new_x: i32, new_y: i32) -> bool {
+pub fn try_move(
+    grid: &mut Grid,
+    elements: &ElementRegistry,
+    x: i32,
+    y: i32,
+    new_x: i32,
+    new_y: i32,
+) -> bool {
     if let (Some(p), Some(tp)) = (grid.get(x, y), grid.get(new_x, new_y)) {
-        // if tp is wall, dont move
-        if tp.element == Element::Wall {
+        // nothing moves into a static element, like a wall. swapping two particles of
+        // the same element changes nothing visible and would keep settled liquids awake
+        if elements.get(tp.element).is_static || tp.element == p.element {
             return false;
-        } else if tp.element == Element::Air {
+        } else if tp.element == Element::AIR {
             grid.swap(x, y, new_x, new_y);
             return true;
         }
 
+        let (p, tp) = (elements.get(p.element), elements.get(tp.element));
+
         if y == new_y {
-            if p.density() >= tp.density() {
+            if p.density >= tp.density {
                 grid.swap(x, y, new_x, new_y);
                 return true;
             }
         } else if new_y > y {
-            if p.density() > tp.density() {
-                grid.swap(x, y, new_x, new_y);
-                return true;
-            }
-        } else if new_y < y {
-            if p.density() < tp.density() {
+            if p.density > tp.density {
                 grid.swap(x, y, new_x, new_y);
                 return true;
             }
+        } else if new_y < y && p.density < tp.density {
+            grid.swap(x, y, new_x, new_y);
+            return true;
         }
     }
     false
 }
 
+/// Tries to move by offset `a` or `b`, picking which to try first at random. Trying
+/// both matters for sleeping chunks: a particle that gave up after one blocked side would
+/// never be looked at again, however open the other side is.
+fn try_move_either(
+    grid: &mut Grid,
+    elements: &ElementRegistry,
+    rng: &mut SimRng,
+    x: i32,
+    y: i32,
+    a: (i32, i32),
+    b: (i32, i32),
+) -> bool {
+    let (first, second) = if rng.gen_range(0..2) == 0 {
+        (a, b)
+    } else {
+        (b, a)
+    };
+    try_move(grid, elements, x, y, x + first.0, y + first.1)
+        || try_move(grid, elements, x, y, x + second.0, y + second.1)
+}
+
 /// Try to move down, if cant, try to move left-down or right-down.
-pub fn fall(grid: &mut Grid, x: i32, y: i32) -> bool {
-    on_floor(y) || try_move(grid, x, y, x, y + 1) || {
-        let mut rng = rand::thread_rng();
-        let direction = rng.gen_range(0..2);
-        if direction == 0 {
-            try_move(grid, x, y, x - 1, y + 1)
-        } else {
-            try_move(grid, x, y, x + 1, y + 1)
-        }
-    }
+pub fn fall(grid: &mut Grid, elements: &ElementRegistry, rng: &mut SimRng, x: i32, y: i32) -> bool {
+    on_floor(grid, y)
+        || try_move(grid, elements, x, y, x, y + 1)
+        || try_move_either(grid, elements, rng, x, y, (-1, 1), (1, 1))
 }
 
 /// Like fall but up instead of down.
-pub fn fall_up(grid: &mut Grid, x: i32, y: i32) -> bool {
-    on_ceiling(y) || try_move(grid, x, y, x, y - 1) || {
-        let mut rng = rand::thread_rng();
-        let direction = rng.gen_range(0..2);
-        if direction == 0 {
-            try_move(grid, x, y, x - 1, y - 1)
-        } else {
-            try_move(grid, x, y, x + 1, y - 1)
-        }
-    }
+pub fn fall_up(
+    grid: &mut Grid,
+    elements: &ElementRegistry,
+    rng: &mut SimRng,
+    x: i32,
+    y: i32,
+) -> bool {
+    on_ceiling(y)
+        || try_move(grid, elements, x, y, x, y - 1)
+        || try_move_either(grid, elements, rng, x, y, (-1, -1), (1, -1))
 }
 
 /// Like try fall but just for left right.
-pub fn jitter_left_right(grid: &mut Grid, x: i32, y: i32) -> bool {
-    let mut rng = rand::thread_rng();
-    let direction = rng.gen_range(0..2);
-    if direction == 0 {
-        try_move(grid, x, y, x - 1, y)
-    } else {
-        try_move(grid, x, y, x + 1, y)
-    }
+pub fn jitter_left_right(
+    grid: &mut Grid,
+    elements: &ElementRegistry,
+    rng: &mut SimRng,
+    x: i32,
+    y: i32,
+) -> bool {
+    try_move_either(grid, elements, rng, x, y, (-1, 0), (1, 0))
 }
 
 /// Same as try_jitter but in all 8 directions.
-pub fn expand_jitter(grid: &mut Grid, x: i32, y: i32) -> bool {
-    let mut rng = rand::thread_rng();
+pub fn expand_jitter(
+    grid: &mut Grid,
+    elements: &ElementRegistry,
+    rng: &mut SimRng,
+    x: i32,
+    y: i32,
+) -> bool {
     let direction = rng.gen_range(0..8);
     let (dx, dy) = DIRECTIONS[direction];
-    try_move(grid, x, y, x + dx, y + dy)
+    try_move(grid, elements, x, y, x + dx, y + dy)
 }
 
 /// Check for nearby collision.
@@ -134,103 +168,303 @@ pub fn check_nearby_for(grid: &mut Grid, x: i32, y: i32, element: Element) -> bo
     false
 }
 
-pub fn step_particles(grid: &mut Grid, frame_clock: u32) {
-    for y in 0..Grid::HEIGHT {
-        for x in 0..Grid::WIDTH {
-            {
-                let p = grid.get_mut(x, y);
-                if let Some(p) = p {
-                    if p.last_ticked == frame_clock {
-                        continue;
-                    } else {
-                        if p.lifetime() > 0 && p.age > p.lifetime() {
-                            grid.set(x, y, Particle::new(Element::Air, frame_clock));
-                            continue;
-                        }
-                        p.last_ticked = frame_clock;
-                        p.age += 1;
-                    }
-                }
-                if let Some(p) = grid.get(x, y) {
-                    match p.element {
-                        Element::Air => { /*  do nothing */ }
-                        Element::Sand => {
-                            fall(grid, x, y);
-                        }
-                        Element::Oil => {
-                            let _ = fall(grid, x, y) || jitter_left_right(grid, x, y);
-                        }
-                        Element::Water => {
-                            if check_nearby_for(grid, x, y, Element::Fire)
-                                || check_nearby_for(grid, x, y, Element::Lava)
-                            {
-                                grid.set(x, y, Particle::new(Element::Steam, frame_clock));
-                            }
-                            let _ = fall(grid, x, y) || jitter_left_right(grid, x, y);
-                        }
-                        Element::Fire => {
-                            let _ = fall_up(grid, x, y) || jitter_left_right(grid, x, y);
-                            let mut rng = rand::thread_rng();
-                            let chance = rng.gen_range(0..16);
-                            if chance == 0 {
-                                grid.set(x, y, Particle::new(Element::Smoke, frame_clock));
-                            }
-                        }
-                        Element::Smoke => {
-                            let _ = fall_up(grid, x, y) || jitter_left_right(grid, x, y);
-                        }
-                        Element::Steam => {
-                            if check_nearby_for(grid, x, y, Element::Ice) {
-                                grid.set(x, y, Particle::new(Element::Water, frame_clock));
-                            }
-                            let _ = fall_up(grid, x, y) || jitter_left_right(grid, x, y);
-                        }
-                        Element::Wood => {
-                            // if check_nearby_for(
-                            //     Element::Fire,
-                            //     p,
-                            //     &particles[0..i],
-                            //     &particles[i + 1..],
-                            //     grid,
-                            // ) {
-                            //     let p = &mut particles[i];
-                            //     p.remove = true;
-                            //     try_spawn_particle(
-                            //         p.x,
-                            //         p.y,
-                            //         Element::Fire,
-                            //         new_particles,
-                            //         grid,
-                            //         next_particle_id,
-                            //     );
-                            // }
-                        }
-                        Element::Wall => { /* Update wall behavior */ }
-                        Element::Ice => {
-                            fall(grid, x, y);
-                        }
-                        Element::Lava => {
-                            let _ = fall(grid, x, y) || jitter_left_right(grid, x, y);
-                            let mut rng = rand::thread_rng();
-                            let chance = rng.gen_range(0..16);
-                            if chance == 0 {
-                                set_if_empty(
-                                    grid,
-                                    x,
-                                    y - 1,
-                                    Particle::new(Element::Fire, frame_clock),
-                                );
-                            }
-                        }
-                    }
-                    let p = grid.get_mut(x, y);
-                    if let Some(p) = p {
-                        // p.age += 1;
-                        // if p.lifetime() > 0 && p.age >= p.lifetime() {
-                        //     p.remove = true;
-                        // }
+/// Melts, boils, freezes or condenses the particle if its temperature has crossed one of
+/// its element's thresholds. The particle keeps its temperature.
+pub fn change_phase(p: &mut Particle, elements: &ElementRegistry) -> bool {
+    let def = elements.get(p.element);
+    let change = match (def.heats_into, def.cools_into) {
+        (Some(change), _) if p.temperature > change.at => change,
+        (_, Some(change)) if p.temperature < change.at => change,
+        _ => return false,
+    };
+    *p = Particle {
+        temperature: p.temperature,
+        seed: p.seed,
+        ..Particle::new(change.becomes, p.last_ticked)
+    };
+    true
+}
+
+/// Sets a flammable particle alight when a neighbour is burning or ignites things (like
+/// Fire or Lava), or when it is hotter than its ignition temperature. A burning particle
+/// throws off flames and smoke, and once its burn time is up it turns into its residue.
+/// Returns true if the particle burnt out this tick.
+pub fn combust(
+    grid: &mut Grid,
+    elements: &ElementRegistry,
+    rng: &mut SimRng,
+    x: i32,
+    y: i32,
+    frame_clock: u32,
+) -> bool {
+    let p = match grid.get(x, y) {
+        Some(p) => *p,
+        None => return false,
+    };
+    let burns = match elements.get(p.element).burns {
+        Some(burns) => burns,
+        None => return false,
+    };
+
+    if p.burning == 0 {
+        let hot_enough = burns
+            .ignition_temperature
+            .is_some_and(|t| p.temperature > t);
+        let caught = hot_enough
+            || DIRECTIONS
+                .iter()
+                .any(|(dx, dy)| match grid.get(x + dx, y + dy) {
+                    Some(n) if n.burning > 0 || elements.get(n.element).ignites => {
+                        rng.gen_bool(burns.chance)
                     }
-                }
+                    _ => false,
+                });
+        if caught {
+            if let Some(p) = grid.get_mut(x, y) {
+                p.burning = burns.duration;
+            }
+            grid.mark_dirty(x, y);
+        }
+        return false;
+    }
+
+    if p.burning == 1 {
+        // the residue keeps the heat of the fire
+        let residue = burns.residue.unwrap_or(Element::AIR);
+        let temperature = p.temperature;
+        grid.set(
+            x,
+            y,
+            Particle {
+                temperature,
+                seed: p.seed,
+                ..Particle::new(residue, frame_clock)
+            },
+        );
+        return true;
+    }
+
+    if let Some(p) = grid.get_mut(x, y) {
+        p.burning -= 1;
+        p.temperature = p.temperature.max(burns.temperature);
+    }
+    grid.mark_dirty(x, y);
+    if let Some(flame) = burns.flame {
+        if rng.gen_bool(burns.flame_chance) {
+            let (dx, dy) = DIRECTIONS[rng.gen_range(0..DIRECTIONS.len())];
+            let (fx, fy) = (x + dx, y + dy);
+            set_if_empty(grid, fx, fy, elements.spawn(flame, frame_clock, fx, fy));
+        }
+    }
+    if let Some(smoke) = burns.smoke {
+        if rng.gen_bool(burns.smoke_chance) {
+            set_if_empty(grid, x, y - 1, elements.spawn(smoke, frame_clock, x, y - 1));
+        }
+    }
+    false
+}
+
+/// Returns true if any neighbour of (x, y) is flammable and not yet burning, and keeps
+/// those neighbours awake. Fire and Lava use this to stay next to fuel; the fuel rolls
+/// its own odds of catching from them in `combust`, once per neighbour each tick.
+pub fn next_to_unlit_fuel(grid: &mut Grid, elements: &ElementRegistry, x: i32, y: i32) -> bool {
+    let mut unlit = false;
+    for (dx, dy) in DIRECTIONS.iter() {
+        if let Some(n) = grid.get(x + dx, y + dy) {
+            if n.burning == 0 && elements.get(n.element).burns.is_some() {
+                grid.mark_dirty(x + dx, y + dy);
+                unlit = true;
+            }
+        }
+    }
+    unlit
+}
+
+/// Applies the first reaction whose partner is found among the neighbours of (x, y).
+/// Neighbours are visited in DIRECTIONS order and, for each, the reactions in file order.
+pub fn react(
+    grid: &mut Grid,
+    elements: &ElementRegistry,
+    rng: &mut SimRng,
+    x: i32,
+    y: i32,
+    frame_clock: u32,
+) -> bool {
+    let element = match grid.get(x, y) {
+        Some(p) => p.element,
+        None => return false,
+    };
+    let reactions = &elements.get(element).reactions;
+    for (dx, dy) in DIRECTIONS.iter() {
+        let (nx, ny) = (x + dx, y + dy);
+        let neighbour = match grid.get(nx, ny) {
+            Some(n) => n.element,
+            None => continue,
+        };
+        for reaction in reactions.iter().filter(|r| r.with == neighbour) {
+            if reaction.probability < 1.0 && !rng.gen_bool(reaction.probability) {
+                // stay awake to try again next tick
+                grid.mark_dirty(x, y);
+                continue;
+            }
+            if let Some(becomes) = reaction.becomes {
+                grid.set(x, y, elements.spawn(becomes, frame_clock, x, y));
+            }
+            if let Some(other_becomes) = reaction.other_becomes {
+                grid.set(nx, ny, elements.spawn(other_becomes, frame_clock, nx, ny));
+            }
+            return true;
+        }
+    }
+    false
+}
+
+/// Advances every particle in the awake parts of the grid by one tick. All randomness is
+/// drawn from `rng`, so the same grid, rng state and frame_clock always produce the same
+/// result, however many threads run it. Call `Grid::begin_step` first.
+///
+/// Chunks are updated in four passes, in a checkerboard: each pass takes every other chunk
+/// in both directions, so no two chunks in a pass are closer than a chunk apart. Nothing a
+/// particle does reaches further than the next cell over, so the chunks of a pass can be
+/// updated at the same time, each on its own copy of its awake rect plus a one cell border.
+/// Each chunk draws from its own stream of a seed taken from `rng`, so the outcome doesn't
+/// depend on which thread gets there first.
+pub fn step_particles(
+    grid: &mut Grid,
+    elements: &ElementRegistry,
+    rng: &mut SimRng,
+    frame_clock: u32,
+) {
+    let seed: u64 = rng.gen();
+    let (chunks_x, chunks_y) = (grid.chunks().chunks_x(), grid.chunks().chunks_y());
+    for (px, py) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+        let jobs: Vec<(u64, DirtyRect, DirtyRect)> = (py..chunks_y)
+            .step_by(2)
+            .flat_map(|cy| (px..chunks_x).step_by(2).map(move |cx| (cx, cy)))
+            .filter_map(|(cx, cy)| {
+                let rect = grid.chunks().active_rect(cx, cy)?;
+                let region = rect.grow(1).intersect(grid.chunks().bounds_of_world())?;
+                Some(((cy * chunks_x + cx) as u64, rect, region))
+            })
+            .collect();
+
+        let updated: Vec<(DirtyRect, Grid)> = jobs
+            .into_par_iter()
+            .map(|(stream, rect, region)| {
+                let mut part = grid.extract(region);
+                let mut rng = SimRng::seed_from_u64(seed);
+                rng.set_stream(stream);
+                let local = rect.offset(-region.x0, -region.y0);
+                step_rect(&mut part, elements, &mut rng, local, frame_clock);
+                (region, part)
+            })
+            .collect();
+
+        for (region, part) in updated {
+            grid.paste(&part, region.x0, region.y0);
+        }
+    }
+}
+
+/// Advances the particles in `rect` one tick, in reading order.
+pub fn step_rect(
+    grid: &mut Grid,
+    elements: &ElementRegistry,
+    rng: &mut SimRng,
+    rect: DirtyRect,
+    frame_clock: u32,
+) {
+    for y in rect.y0..=rect.y1 {
+        for x in rect.x0..=rect.x1 {
+            step_particle(grid, elements, rng, x, y, frame_clock);
+        }
+    }
+}
+
+/// Advances the particle at (x, y) by one tick, unless it already moved this tick.
+pub fn step_particle(
+    grid: &mut Grid,
+    elements: &ElementRegistry,
+    rng: &mut SimRng,
+    x: i32,
+    y: i32,
+    frame_clock: u32,
+) {
+    let p = match grid.get_mut(x, y) {
+        Some(p) => p,
+        None => return,
+    };
+    if p.last_ticked == frame_clock {
+        // already moved this tick, or was placed with this tick's stamp; either way it
+        // still needs a turn next tick
+        grid.mark_dirty(x, y);
+        return;
+    }
+    let lifetime = elements.get(p.element).lifetime;
+    if lifetime > 0 && p.age > lifetime {
+        // the air left behind keeps the heat of what burnt out
+        *p = Particle {
+            temperature: p.temperature,
+            ..Particle::new(Element::AIR, frame_clock)
+        };
+        grid.mark_dirty(x, y);
+        return;
+    }
+    p.last_ticked = frame_clock;
+    p.age += 1;
+    let changed = change_phase(p, elements);
+    let element = p.element;
+    let def = elements.get(element);
+    if changed || lifetime > 0 {
+        // particles that will expire have to be kept awake until they do
+        grid.mark_dirty(x, y);
+    }
+
+    if def.burns.is_some() && combust(grid, elements, rng, x, y, frame_clock) {
+        // burnt out
+        return;
+    }
+
+    // flames cling to fuel until it catches, rather than rising off it after one try;
+    // only short-lived or rising igniters do, so Lava still flows past wood
+    let flame = def.lifetime > 0 || matches!(def.movement, Movement::Gas);
+    let clinging = def.ignites && flame && next_to_unlit_fuel(grid, elements, x, y);
+
+    if !def.reactions.is_empty()
+        && react(grid, elements, rng, x, y, frame_clock)
+        && grid.get(x, y).map(|p| p.element) != Some(element)
+    {
+        // turned into something else, which takes its first turn next tick
+        return;
+    }
+
+    match def.movement {
+        _ if clinging => {}
+        Movement::None => { /*  do nothing */ }
+        Movement::Powder => {
+            fall(grid, elements, rng, x, y);
+        }
+        Movement::Liquid => {
+            let _ = fall(grid, elements, rng, x, y) || jitter_left_right(grid, elements, rng, x, y);
+        }
+        Movement::Gas => {
+            let _ =
+                fall_up(grid, elements, rng, x, y) || jitter_left_right(grid, elements, rng, x, y);
+        }
+    }
+
+    if !def.emits.is_empty() {
+        // emitters may go off on any tick
+        grid.mark_dirty(x, y);
+    }
+    for emission in &def.emits {
+        if rng.gen_range(0..emission.chance) == 0 {
+            let (dx, dy) = emission.offset;
+            let spawned = elements.spawn(emission.element, frame_clock, x + dx, y + dy);
+            if emission.overwrite {
+                grid.set(x + dx, y + dy, spawned);
+            } else {
+                set_if_empty(grid, x + dx, y + dy, spawned);
             }
         }
     }
@@ -241,3 +475,111 @@ pub fn step_particles(grid: &mut Grid, frame_clock: u32) {
     //     p.remove = true;
     // }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::tests::{name_at, world_with};
+
+    /// Elements A to D that don't move unless `a_moves`, and one reaction between A and B.
+    fn reacting(reaction: &str, a_moves: bool) -> ElementRegistry {
+        let movement = if a_moves { "Powder" } else { "None" };
+        ElementRegistry::from_ron(&format!(
+            r#"(
+                elements: [
+                    (name: "Air", color: (0, 0, 0, 0)),
+                    (name: "A", color: (255, 0, 0, 255), movement: {}),
+                    (name: "B", color: (0, 255, 0, 255)),
+                    (name: "C", color: (0, 0, 255, 255), movement: Powder),
+                    (name: "D", color: (255, 255, 0, 255)),
+                ],
+                reactions: [(a: "A", b: "B", {})],
+            )"#,
+            movement, reaction
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn reaction_replaces_both_particles() {
+        let elements = reacting(r#"a_becomes: "C", b_becomes: "D""#, true);
+        let mut world = world_with(elements, &[(4, 3, "A"), (4, 4, "B")]);
+        world.step();
+        assert_eq!(name_at(&world, 4, 3), "C");
+        assert_eq!(name_at(&world, 4, 4), "D");
+    }
+
+    #[test]
+    fn replaced_particle_waits_for_its_next_turn() {
+        // C falls, but only from the tick after A turned into it
+        let elements = reacting(r#"a_becomes: "C""#, false);
+        let mut world = world_with(elements, &[(4, 3, "A"), (5, 3, "B")]);
+        world.step();
+        assert_eq!(name_at(&world, 4, 3), "C");
+        world.step();
+        assert_eq!(name_at(&world, 4, 3), "Air");
+        assert_eq!(name_at(&world, 4, 4), "C");
+    }
+
+    #[test]
+    fn replaced_particle_stops_moving_as_its_old_element() {
+        // A falls, D doesn't
+        let elements = reacting(r#"a_becomes: "D""#, true);
+        let mut world = world_with(elements, &[(4, 3, "A"), (5, 3, "B")]);
+        for _ in 0..5 {
+            world.step();
+        }
+        assert_eq!(name_at(&world, 4, 3), "D");
+    }
+
+    #[test]
+    fn reactions_need_contact() {
+        let elements = reacting(r#"a_becomes: "C""#, false);
+        let mut world = world_with(elements, &[(1, 1, "A"), (5, 5, "B")]);
+        for _ in 0..20 {
+            world.step();
+        }
+        assert_eq!(name_at(&world, 1, 1), "A");
+    }
+
+    #[test]
+    fn probability_decides_how_often_a_reaction_happens() {
+        let never = reacting(r#"probability: 0.0, a_becomes: "C""#, false);
+        let mut world = world_with(never, &[(4, 4, "A"), (5, 4, "B")]);
+        for _ in 0..100 {
+            world.step();
+        }
+        assert_eq!(name_at(&world, 4, 4), "A");
+
+        // an even chance each tick misses 100 times in a row with odds of 2^-100
+        let sometimes = reacting(r#"probability: 0.5, a_becomes: "D""#, false);
+        let mut world = world_with(sometimes, &[(4, 4, "A"), (5, 4, "B")]);
+        for _ in 0..100 {
+            world.step();
+        }
+        assert_eq!(name_at(&world, 4, 4), "D");
+    }
+
+    #[test]
+    fn a_spark_stays_on_wood_until_it_catches() {
+        let mut world = world_with(
+            ElementRegistry::builtin(),
+            &[(4, 14, "Fire"), (4, 15, "Wood")],
+        );
+        for _ in 0..200 {
+            world.step();
+        }
+        assert_eq!(name_at(&world, 4, 15), "Ash");
+    }
+
+    #[test]
+    fn lava_falls_past_wood() {
+        let mut cells: Vec<_> = (0..16).map(|y| (5, y, "Wood")).collect();
+        cells.push((4, 0, "Lava"));
+        let mut world = world_with(ElementRegistry::builtin(), &cells);
+        for _ in 0..20 {
+            world.step();
+        }
+        assert_eq!(name_at(&world, 4, 15), "Lava");
+    }
+}
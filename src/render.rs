@@ -1,46 +1,116 @@
-use sdl2::pixels::Color;
-use sdl2::rect::Point;
-use sdl2::render::Texture;
-use sdl2::video::Window;
-use sdl2::{rect::Rect, render::Canvas};
-
-use crate::grid::Grid;
-use crate::particle::Particle;
-use crate::settings::{WINDOW_HEIGHT, WINDOW_WIDTH};
+use fallingsand::bloom::Bloom;
+use fallingsand::brush::Brush;
+use fallingsand::camera::Camera;
+use fallingsand::color;
+use fallingsand::lighting::Lighting;
+use fallingsand::world::World;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::render::{Texture, TextureCreator};
+use sdl2::video::{Window, WindowContext};
 
 /*
-    Particle renduring should be done by operating on the intermediary canvas directly, likely
-    likely the data should be treating the 2d buffer as the grid itself, with perhaps one full copy per frame.
-    // how can i do a single draw call for all particles?
-
+    Particles are drawn into a CPU-side RGBA buffer with one pixel per cell, which is
+    uploaded to a streaming texture the size of the grid once per frame and then scaled up
+    to where the camera puts the grid. The cost is one upload and one copy whatever the number of cells.
 */
 
+/// Converts a simulation colour into the SDL equivalent.
+pub fn sdl_color(c: color::Color) -> Color {
+    Color::RGBA(c.r, c.g, c.b, c.a)
+}
+
+/// A streaming texture with one pixel per cell of a `width` x `height` grid, in the byte
+/// order `World::fill_rgba` writes.
+pub fn create_grid_texture(
+    texture_creator: &TextureCreator<WindowContext>,
+    width: u32,
+    height: u32,
+) -> Texture<'_> {
+    texture_creator
+        .create_texture_streaming(PixelFormatEnum::RGBA32, width, height)
+        .unwrap()
+}
+
+/// Draws the grid as the camera sees it, lit by `lighting`, with the glow of emissive
+/// particles if `bloom` is given. `pixels` is scratch space kept between frames so the
+/// buffer isn't reallocated every frame.
 pub fn render_particles(
     canvas: &mut Canvas<Window>,
-    intermediary_canvas: &mut Texture,
-    grid: &Grid,
+    camera: &Camera,
+    grid_texture: &mut Texture,
+    world: &World,
+    lighting: &mut Lighting,
+    bloom: Option<&mut Bloom>,
+    pixels: &mut Vec<u8>,
 ) {
-    canvas
-        .with_texture_canvas(intermediary_canvas, |texture_canvas| {
-            texture_canvas.set_draw_color(Color::RGB(0, 0, 0));
-            texture_canvas.clear();
-
-            for (x, y, p) in grid.iter() {
-                texture_canvas.set_draw_color(p.color());
-                let _ = texture_canvas.draw_point(Point::new(x as i32, y as i32));
-            }
-        })
+    world.fill_rgba(pixels);
+    lighting.apply(world, pixels);
+    if let Some(bloom) = bloom {
+        bloom.apply(world, pixels);
+    }
+    grid_texture
+        .update(None, pixels, world.grid.width() as usize * 4)
         .unwrap();
 
     canvas.set_draw_color(Color::RGB(0, 0, 0));
     canvas.clear();
 
-    // Draw the intermediary canvas onto the main canvas, scaled to the window size
+    // Draw the grid texture onto the main canvas, scaled to the camera's zoom
+    let (width, height) = (world.grid.width(), world.grid.height());
     canvas
         .copy(
-            &intermediary_canvas,
+            grid_texture,
             None,
-            Some(Rect::new(0, 0, WINDOW_WIDTH, WINDOW_HEIGHT)),
+            Rect::from(camera.cells_rect(0, 0, width, height)),
         )
         .unwrap();
 }
+
+/// Outlines the part of each chunk the last step visited, for seeing which regions sleep.
+pub fn render_chunk_overlay(canvas: &mut Canvas<Window>, camera: &Camera, world: &World) {
+    let chunks = world.grid.chunks();
+    canvas.set_draw_color(Color::RGB(0, 255, 0));
+    for cy in 0..chunks.chunks_y() {
+        for cx in 0..chunks.chunks_x() {
+            if let Some(r) = chunks.active_rect(cx, cy) {
+                let _ = canvas.draw_rect(Rect::from(camera.cells_rect(
+                    r.x0,
+                    r.y0,
+                    r.x1 - r.x0 + 1,
+                    r.y1 - r.y0 + 1,
+                )));
+            }
+        }
+    }
+}
+
+/// Outlines the cells the brush would paint if centred on grid cell (x, y).
+pub fn render_brush_outline(
+    canvas: &mut Canvas<Window>,
+    camera: &Camera,
+    brush: &Brush,
+    x: i32,
+    y: i32,
+) {
+    // only the cells on the edge of the footprint, so the outline doesn't hide the grid
+    let edge: Vec<(i32, i32)> = brush
+        .footprint(x, y)
+        .filter(|(cx, cy)| {
+            let (dx, dy) = (cx - x, cy - y);
+            [(0, -1), (-1, 0), (1, 0), (0, 1)]
+                .iter()
+                .any(|(ox, oy)| !brush.covers(dx + ox, dy + oy))
+        })
+        .collect();
+    render_cell_outline(canvas, camera, &edge);
+}
+
+/// Draws a box around each of the given grid cells.
+pub fn render_cell_outline(canvas: &mut Canvas<Window>, camera: &Camera, cells: &[(i32, i32)]) {
+    canvas.set_draw_color(Color::RGB(255, 255, 255));
+    for &(x, y) in cells {
+        let _ = canvas.draw_rect(Rect::from(camera.cells_rect(x, y, 1, 1)));
+    }
+}
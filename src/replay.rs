@@ -0,0 +1,597 @@
+/*
+    A recording is the world as it was when recording started, including the state of its
+    random number generator, plus every action applied since, each tagged with the number
+    of steps taken before it. Replaying applies the actions between the same steps, which
+    reproduces the session exactly since stepping is deterministic.
+
+    The file also keeps a hash of the grid as it was when the recording was saved. A
+    replay that ends on a different grid, because the simulation rules changed since the
+    file was written, is reported rather than passed off as the recorded session.
+
+    Recording wakes every chunk when it starts, because which cells a step visits decides
+    which random numbers they draw, and a loaded grid always starts fully awake. It also
+    clears the undo history, which isn't saved, so that an undo in the recording can only
+    reach edits that are in it too.
+
+    Replay file format, all integers little endian:
+
+        magic           b"FSRP"
+        version         u16
+        element table   as in the world file, for the element ids in actions
+        rng             32 byte key, u64 stream, u128 word position
+        seed            u64
+        frame clock     u32
+        ambient         f32, the ambient temperature
+        ambient light   f32
+        ticks           u32, steps in the recording
+        end hash        u64, see grid_hash
+        world           u64 length + a world file, see save.rs
+        actions         zlib compressed until the end of the file, per action:
+                            tick u32, kind u8, then per kind:
+                            0 Paint          x i32, y i32, element u16
+                            1 SelectElement  element u16
+                            2 Stroke         from x, y i32, to x, y i32, element u16, brush
+                            3 Shape          shape kind u8, then per kind:
+                                                 0 Rect    from x, y i32, to x, y i32,
+                                                           filled u8
+                                                 1 Circle  center x, y i32, radius i32,
+                                                           filled u8
+                                                 2 Fill    x, y i32
+                                             then element u16, brush
+                            4 Clear
+                            5 EndEdit
+                            6 Undo
+                            7 Redo
+                            8 SetAmbientLight level f32
+
+        where a brush is
+                            shape u8 (0 circle, 1 square), radius i32, density f32,
+                            mode u8 (0 overwrite, 1 into air, 2 replace + element u16)
+
+    Actions reaching more than MAX_OUTSIDE_GRID cells outside the world, and brushes or
+    circles too large to paint in reasonable time, make the file fail to load.
+*/
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use rand::SeedableRng;
+
+use crate::{
+    action::Action,
+    brush::{Brush, BrushMode, BrushShape},
+    element::{Element, ElementRegistry},
+    grid::Grid,
+    history::History,
+    save::write_element_table,
+    save::{read_element, read_element_table, read_u16, read_u32, read_u64, read_u8},
+    tools::Shape,
+    world::{SimRng, World},
+};
+
+const MAGIC: &[u8; 4] = b"FSRP";
+
+/// How far outside the grid a replayed action may reach, in cells. Further out is taken
+/// as a damaged file rather than painted.
+const MAX_OUTSIDE_GRID: i64 = 1 << 16;
+
+/// The version written by `Recording::save`.
+pub const REPLAY_VERSION: u16 = 1;
+
+/// A world and the actions applied to it, see the top of this file.
+#[derive(Clone)]
+pub struct Recording {
+    initial: World,
+    actions: Vec<(u32, Action)>,
+    ticks: u32,
+    /// The hash of the grid at the end, for recordings read from a file.
+    end_hash: Option<u64>,
+}
+
+impl Recording {
+    /// Starts recording `world` from its current state.
+    pub fn start(world: &mut World) -> Recording {
+        world.grid.wake_all();
+        world.history.clear();
+        Recording {
+            initial: world.clone(),
+            actions: Vec::new(),
+            ticks: 0,
+            end_hash: None,
+        }
+    }
+
+    /// Notes an action applied before the next step.
+    pub fn record(&mut self, action: Action) {
+        self.actions.push((self.ticks, action));
+    }
+
+    /// Notes that the world was stepped.
+    pub fn tick(&mut self) {
+        self.ticks += 1;
+    }
+
+    pub fn ticks(&self) -> u32 {
+        self.ticks
+    }
+
+    /// The world as it was when recording started, and a playback of what happened to it.
+    pub fn play(&self) -> (World, Playback) {
+        let playback = Playback {
+            actions: self.actions.clone(),
+            next: 0,
+            tick: 0,
+            ticks: self.ticks,
+            end_hash: self.end_hash,
+        };
+        (self.initial.clone(), playback)
+    }
+
+    /// Replays the whole recording, returning the world as it was when recording stopped,
+    /// or an error if it ends differently, see `Playback::check_end`.
+    pub fn replay(&self) -> Result<World, String> {
+        let (mut world, mut playback) = self.play();
+        loop {
+            for (_, action) in playback.advance() {
+                world.apply(action);
+            }
+            if playback.is_finished() {
+                playback.check_end(&world)?;
+                return Ok(world);
+            }
+            world.step();
+        }
+    }
+
+    /// Writes the recording to `path`, with `end` being the recorded world as it is now.
+    pub fn save<P: AsRef<Path>>(&self, path: P, end: &World) -> Result<(), String> {
+        let path = path.as_ref();
+        let file = File::create(path)
+            .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+        self.write_to(BufWriter::new(file), end)
+            .map_err(|e| format!("Failed to save {}: {}", path.display(), e))
+    }
+
+    /// Reads a replay file, building its world with `elements`.
+    pub fn load<P: AsRef<Path>>(path: P, elements: &ElementRegistry) -> Result<Recording, String> {
+        let path = path.as_ref();
+        let file =
+            File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        Recording::read_from(BufReader::new(file), elements)
+            .map_err(|e| format!("Failed to load {}: {}", path.display(), e))
+    }
+
+    pub fn write_to<W: Write>(&self, mut w: W, end: &World) -> Result<(), String> {
+        let io = |e: std::io::Error| e.to_string();
+        let world = &self.initial;
+        w.write_all(MAGIC).map_err(io)?;
+        w.write_all(&REPLAY_VERSION.to_le_bytes()).map_err(io)?;
+        write_element_table(&mut w, &world.elements)?;
+        w.write_all(&world.rng.get_seed()).map_err(io)?;
+        w.write_all(&world.rng.get_stream().to_le_bytes())
+            .map_err(io)?;
+        w.write_all(&world.rng.get_word_pos().to_le_bytes())
+            .map_err(io)?;
+        w.write_all(&world.seed.to_le_bytes()).map_err(io)?;
+        w.write_all(&world.frame_clock.to_le_bytes()).map_err(io)?;
+        w.write_all(&world.ambient_temperature.to_le_bytes())
+            .map_err(io)?;
+        w.write_all(&world.ambient_light.to_le_bytes())
+            .map_err(io)?;
+        w.write_all(&self.ticks.to_le_bytes()).map_err(io)?;
+        w.write_all(&grid_hash(&end.grid).to_le_bytes())
+            .map_err(io)?;
+
+        let mut grid = Vec::new();
+        world.grid.write_to(&mut grid, &world.elements)?;
+        w.write_all(&(grid.len() as u64).to_le_bytes())
+            .map_err(io)?;
+        w.write_all(&grid).map_err(io)?;
+
+        let mut z = ZlibEncoder::new(w, Compression::default());
+        for (tick, action) in &self.actions {
+            z.write_all(&tick.to_le_bytes()).map_err(io)?;
+            match *action {
+                Action::Paint { x, y, element } => {
+                    z.write_all(&[0]).map_err(io)?;
+                    z.write_all(&x.to_le_bytes()).map_err(io)?;
+                    z.write_all(&y.to_le_bytes()).map_err(io)?;
+                    z.write_all(&element.id().to_le_bytes()).map_err(io)?;
+                }
+                Action::SelectElement { element } => {
+                    z.write_all(&[1]).map_err(io)?;
+                    z.write_all(&element.id().to_le_bytes()).map_err(io)?;
+                }
+                Action::Stroke {
+                    from,
+                    to,
+                    element,
+                    brush,
+                } => {
+                    z.write_all(&[2]).map_err(io)?;
+                    for v in [from.0, from.1, to.0, to.1] {
+                        z.write_all(&v.to_le_bytes()).map_err(io)?;
+                    }
+                    z.write_all(&element.id().to_le_bytes()).map_err(io)?;
+                    write_brush(&mut z, &brush)?;
+                }
+                Action::Clear => z.write_all(&[4]).map_err(io)?,
+                Action::EndEdit => z.write_all(&[5]).map_err(io)?,
+                Action::Undo => z.write_all(&[6]).map_err(io)?,
+                Action::Redo => z.write_all(&[7]).map_err(io)?,
+                Action::SetAmbientLight { level } => {
+                    z.write_all(&[8]).map_err(io)?;
+                    z.write_all(&level.to_le_bytes()).map_err(io)?;
+                }
+                Action::Shape {
+                    shape,
+                    element,
+                    brush,
+                } => {
+                    z.write_all(&[3]).map_err(io)?;
+                    let (kind, values, filled) = match shape {
+                        Shape::Rect { from, to, filled } => {
+                            (0, vec![from.0, from.1, to.0, to.1], Some(filled))
+                        }
+                        Shape::Circle {
+                            center,
+                            radius,
+                            filled,
+                        } => (1, vec![center.0, center.1, radius], Some(filled)),
+                        Shape::Fill { x, y } => (2, vec![x, y], None),
+                    };
+                    z.write_all(&[kind]).map_err(io)?;
+                    for v in values {
+                        z.write_all(&v.to_le_bytes()).map_err(io)?;
+                    }
+                    if let Some(filled) = filled {
+                        z.write_all(&[filled as u8]).map_err(io)?;
+                    }
+                    z.write_all(&element.id().to_le_bytes()).map_err(io)?;
+                    write_brush(&mut z, &brush)?;
+                }
+            }
+        }
+        z.finish().map_err(io)?.flush().map_err(io)
+    }
+
+    pub fn read_from<R: Read>(mut r: R, elements: &ElementRegistry) -> Result<Recording, String> {
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic).map_err(|e| e.to_string())?;
+        if &magic != MAGIC {
+            return Err("not a replay file".to_string());
+        }
+        let version = read_u16(&mut r)?;
+        if version != REPLAY_VERSION {
+            return Err(format!("unsupported replay file version {}", version));
+        }
+        let table = read_element_table(&mut r, elements)?;
+
+        let mut key = [0; 32];
+        r.read_exact(&mut key).map_err(|e| e.to_string())?;
+        let mut rng = SimRng::from_seed(key);
+        rng.set_stream(read_u64(&mut r)?);
+        let mut word_pos = [0; 16];
+        r.read_exact(&mut word_pos).map_err(|e| e.to_string())?;
+        rng.set_word_pos(u128::from_le_bytes(word_pos));
+        let seed = read_u64(&mut r)?;
+        let frame_clock = read_u32(&mut r)?;
+        let ambient_temperature = f32::from_bits(read_u32(&mut r)?);
+        let ambient_light = f32::from_bits(read_u32(&mut r)?);
+        let ticks = read_u32(&mut r)?;
+        let end_hash = Some(read_u64(&mut r)?);
+
+        let len = read_u64(&mut r)?;
+        let mut grid = Vec::new();
+        (&mut r)
+            .take(len)
+            .read_to_end(&mut grid)
+            .map_err(|e| e.to_string())?;
+        let grid = Grid::read_from(&grid[..], elements)?;
+        let in_reach = |(x, y): (i32, i32)| {
+            let reach = |v: i32, size: i32| {
+                (-MAX_OUTSIDE_GRID..size as i64 + MAX_OUTSIDE_GRID).contains(&(v as i64))
+            };
+            reach(x, grid.width()) && reach(y, grid.height())
+        };
+
+        let mut actions = Vec::new();
+        let mut z = ZlibDecoder::new(r);
+        loop {
+            // a clean end of file between actions is the end of the recording
+            let mut tick = [0; 4];
+            match z.read(&mut tick[..1]).map_err(|e| e.to_string())? {
+                0 => break,
+                _ => z.read_exact(&mut tick[1..]).map_err(|e| e.to_string())?,
+            }
+            let tick = u32::from_le_bytes(tick);
+            let action = match read_u8(&mut z)? {
+                0 => Action::Paint {
+                    x: read_u32(&mut z)? as i32,
+                    y: read_u32(&mut z)? as i32,
+                    element: read_element(&mut z, &table)?,
+                },
+                1 => Action::SelectElement {
+                    element: read_element(&mut z, &table)?,
+                },
+                2 => Action::Stroke {
+                    from: read_point(&mut z)?,
+                    to: read_point(&mut z)?,
+                    element: read_element(&mut z, &table)?,
+                    brush: read_brush(&mut z, &table)?,
+                },
+                3 => Action::Shape {
+                    shape: match read_u8(&mut z)? {
+                        0 => Shape::Rect {
+                            from: read_point(&mut z)?,
+                            to: read_point(&mut z)?,
+                            filled: read_u8(&mut z)? != 0,
+                        },
+                        1 => Shape::Circle {
+                            center: read_point(&mut z)?,
+                            radius: read_u32(&mut z)? as i32,
+                            filled: read_u8(&mut z)? != 0,
+                        },
+                        2 => {
+                            let (x, y) = read_point(&mut z)?;
+                            Shape::Fill { x, y }
+                        }
+                        shape => return Err(format!("unknown shape {}", shape)),
+                    },
+                    element: read_element(&mut z, &table)?,
+                    brush: read_brush(&mut z, &table)?,
+                },
+                4 => Action::Clear,
+                5 => Action::EndEdit,
+                6 => Action::Undo,
+                7 => Action::Redo,
+                8 => Action::SetAmbientLight {
+                    level: f32::from_bits(read_u32(&mut z)?),
+                },
+                kind => return Err(format!("unknown action {}", kind)),
+            };
+            check_action(&action, in_reach)?;
+            actions.push((tick, action));
+        }
+
+        let initial = World {
+            grid,
+            elements: elements.clone(),
+            rng,
+            seed,
+            frame_clock,
+            ambient_temperature,
+            ambient_light,
+            history: History::default(),
+        };
+        Ok(Recording {
+            initial,
+            actions,
+            ticks,
+            end_hash,
+        })
+    }
+}
+
+fn write_brush<W: Write>(w: &mut W, brush: &Brush) -> Result<(), String> {
+    let io = |e: std::io::Error| e.to_string();
+    let shape: u8 = match brush.shape {
+        BrushShape::Circle => 0,
+        BrushShape::Square => 1,
+    };
+    w.write_all(&[shape]).map_err(io)?;
+    w.write_all(&brush.radius.to_le_bytes()).map_err(io)?;
+    w.write_all(&brush.density.to_le_bytes()).map_err(io)?;
+    match brush.mode {
+        BrushMode::Overwrite => w.write_all(&[0]).map_err(io),
+        BrushMode::IntoAir => w.write_all(&[1]).map_err(io),
+        BrushMode::Replace(target) => {
+            w.write_all(&[2]).map_err(io)?;
+            w.write_all(&target.id().to_le_bytes()).map_err(io)
+        }
+    }
+}
+
+fn read_brush<R: Read>(r: &mut R, table: &[Element]) -> Result<Brush, String> {
+    Ok(Brush {
+        shape: match read_u8(r)? {
+            0 => BrushShape::Circle,
+            1 => BrushShape::Square,
+            shape => return Err(format!("unknown brush shape {}", shape)),
+        },
+        radius: read_u32(r)? as i32,
+        density: f32::from_bits(read_u32(r)?),
+        mode: match read_u8(r)? {
+            0 => BrushMode::Overwrite,
+            1 => BrushMode::IntoAir,
+            2 => BrushMode::Replace(read_element(r, table)?),
+            mode => return Err(format!("unknown brush mode {}", mode)),
+        },
+    })
+}
+
+/// Refuses actions with coordinates too far outside the grid, or sizes that would take
+/// too long to paint.
+fn check_action(action: &Action, in_reach: impl Fn((i32, i32)) -> bool) -> Result<(), String> {
+    let check_brush = |brush: &Brush| match brush.radius {
+        0..=Brush::MAX_RADIUS => Ok(()),
+        radius => Err(format!("brush radius {} out of range", radius)),
+    };
+    let points = match *action {
+        Action::Paint { x, y, .. } => vec![(x, y)],
+        Action::Stroke {
+            from, to, brush, ..
+        } => {
+            check_brush(&brush)?;
+            vec![from, to]
+        }
+        Action::Shape { shape, brush, .. } => {
+            check_brush(&brush)?;
+            match shape {
+                Shape::Rect { from, to, .. } => vec![from, to],
+                Shape::Circle { center, radius, .. } => {
+                    if !(0..=MAX_OUTSIDE_GRID).contains(&(radius as i64)) {
+                        return Err(format!("circle radius {} out of range", radius));
+                    }
+                    vec![center]
+                }
+                Shape::Fill { x, y } => vec![(x, y)],
+            }
+        }
+        _ => Vec::new(),
+    };
+    match points.into_iter().find(|&p| !in_reach(p)) {
+        Some((x, y)) => Err(format!("action at ({}, {}) is outside the world", x, y)),
+        None => Ok(()),
+    }
+}
+
+/// A hash of the size and every saved part of every cell, FNV-1a so that it is the same
+/// on every platform and in every build.
+pub fn grid_hash(grid: &Grid) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut add = |bytes: &[u8]| {
+        for &b in bytes {
+            hash = (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    };
+    add(&grid.width().to_le_bytes());
+    add(&grid.height().to_le_bytes());
+    for p in grid.cells() {
+        add(&p.element.id().to_le_bytes());
+        add(&p.age.to_le_bytes());
+        add(&p.temperature.to_bits().to_le_bytes());
+        add(&p.burning.to_le_bytes());
+        add(&[p.seed]);
+    }
+    hash
+}
+
+fn read_point<R: Read>(r: &mut R) -> Result<(i32, i32), String> {
+    Ok((read_u32(r)? as i32, read_u32(r)? as i32))
+}
+
+/// Hands out the actions of a recording one step at a time.
+pub struct Playback {
+    actions: Vec<(u32, Action)>,
+    next: usize,
+    tick: u32,
+    ticks: u32,
+    end_hash: Option<u64>,
+}
+
+impl Playback {
+    /// The actions to apply before the next step, then moves on to the step after it.
+    pub fn advance(&mut self) -> &[(u32, Action)] {
+        let start = self.next;
+        while self
+            .actions
+            .get(self.next)
+            .is_some_and(|(t, _)| *t <= self.tick)
+        {
+            self.next += 1;
+        }
+        self.tick += 1;
+        &self.actions[start..self.next]
+    }
+
+    /// True once the actions after the last recorded step have been handed out. The world
+    /// shouldn't be stepped again as part of the recording.
+    pub fn is_finished(&self) -> bool {
+        self.tick > self.ticks
+    }
+
+    /// Checks that `world`, played back to the end, has the grid the recording ended
+    /// with. Recordings that weren't read from a file have no end to check, and pass.
+    pub fn check_end(&self, world: &World) -> Result<(), String> {
+        match self.end_hash {
+            Some(hash) if hash != grid_hash(&world.grid) => Err(
+                "the replay ended differently from the recording; the simulation has changed \
+                 since it was recorded"
+                    .to_string(),
+            ),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::tests::{busy_world, cell_states};
+
+    fn perform(world: &mut World, recording: &mut Recording, action: Action) {
+        world.apply(&action);
+        recording.record(action);
+    }
+
+    /// Records a session of strokes, shapes, undo and redo on a busy world, returning the
+    /// recording and the live world at its end.
+    fn session() -> (Recording, World) {
+        let mut world = busy_world(5);
+        let mut recording = Recording::start(&mut world);
+        let sand = world.elements.by_name("Sand").unwrap();
+        let water = world.elements.by_name("Water").unwrap();
+        for tick in 0..60 {
+            if tick % 10 == 0 {
+                let stroke = Action::Stroke {
+                    from: (tick, 5),
+                    to: (tick + 20, 20),
+                    element: sand,
+                    brush: Brush::default(),
+                };
+                perform(&mut world, &mut recording, stroke);
+                perform(&mut world, &mut recording, Action::EndEdit);
+            }
+            match tick {
+                25 => perform(&mut world, &mut recording, Action::Undo),
+                35 => perform(&mut world, &mut recording, Action::Redo),
+                45 => perform(
+                    &mut world,
+                    &mut recording,
+                    Action::Shape {
+                        shape: Shape::Fill { x: 2, y: 2 },
+                        element: water,
+                        brush: Brush::default(),
+                    },
+                ),
+                _ => {}
+            }
+            world.step();
+            recording.tick();
+        }
+        perform(
+            &mut world,
+            &mut recording,
+            Action::SetAmbientLight { level: 0.5 },
+        );
+        (recording, world)
+    }
+
+    #[test]
+    fn saved_recording_replays_to_the_live_world() {
+        let (recording, live) = session();
+        let mut bytes = Vec::new();
+        recording.write_to(&mut bytes, &live).unwrap();
+        let loaded = Recording::read_from(&bytes[..], &live.elements).unwrap();
+        let replayed = loaded.replay().unwrap();
+        assert!(cell_states(&replayed.grid) == cell_states(&live.grid));
+        assert!(replayed.rng == live.rng);
+        assert_eq!(replayed.ambient_light, 0.5);
+    }
+
+    #[test]
+    fn replay_that_ends_differently_is_an_error() {
+        let (recording, mut live) = session();
+        // as if the rules had changed: the recorded end is a tick further on
+        live.step();
+        let mut bytes = Vec::new();
+        recording.write_to(&mut bytes, &live).unwrap();
+        let loaded = Recording::read_from(&bytes[..], &live.elements).unwrap();
+        assert!(loaded.replay().is_err());
+    }
+}
@@ -0,0 +1,370 @@
+/*
+    World file format, all integers little endian:
+
+        magic           b"FSND"
+        version         u16
+        width, height   u32, u32
+        ambient         f32 temperature, f32 light
+        element count   u16
+        element names   u16 length + UTF-8 bytes each, in the order of the ids used below
+        cells           zlib compressed, row-major, per cell:
+                            element u16, age u32, temperature f32, burning u32,
+                            colour seed u8
+
+    Elements are stored by name so a file still loads after elements are added to or
+    reordered in assets/elements.ron. `last_ticked` is scheduling state for the step in
+    progress and is not saved.
+
+    Worlds of more than MAX_WORLD_CELLS cells are refused when loading, so a corrupt size
+    can't make the reader allocate without bound.
+*/
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::{
+    element::{Element, ElementRegistry},
+    grid::Grid,
+    particle::{Particle, ROOM_TEMPERATURE},
+    world::World,
+};
+
+const MAGIC: &[u8; 4] = b"FSND";
+
+/// The version written by `Grid::save` and `World::save`.
+pub const FORMAT_VERSION: u16 = 1;
+
+/// Files claiming more cells than this are refused rather than allocated.
+pub const MAX_WORLD_CELLS: u64 = 1 << 24;
+
+/// The widest or tallest world that can be created from the command line or a PNG.
+pub const MAX_WORLD_SIDE: u32 = 4096;
+
+impl Grid {
+    /// Writes the grid to a world file at `path`, with the default ambient temperature
+    /// and light. `World::save` keeps the world's own.
+    pub fn save<P: AsRef<Path>>(&self, path: P, elements: &ElementRegistry) -> Result<(), String> {
+        let path = path.as_ref();
+        let file = File::create(path)
+            .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+        self.write_to(BufWriter::new(file), elements)
+            .map_err(|e| format!("Failed to save {}: {}", path.display(), e))
+    }
+
+    /// Reads the cells of a world file written by `save`, with every chunk awake.
+    pub fn load<P: AsRef<Path>>(path: P, elements: &ElementRegistry) -> Result<Grid, String> {
+        let path = path.as_ref();
+        let file =
+            File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        Grid::read_from(BufReader::new(file), elements)
+            .map_err(|e| format!("Failed to load {}: {}", path.display(), e))
+    }
+
+    pub fn write_to<W: Write>(&self, w: W, elements: &ElementRegistry) -> Result<(), String> {
+        write_world(w, self, elements, Ambient::default())
+    }
+
+    pub fn read_from<R: Read>(r: R, elements: &ElementRegistry) -> Result<Grid, String> {
+        read_world(r, elements).map(|(grid, _)| grid)
+    }
+}
+
+impl World {
+    /// Writes the grid and the ambient temperature and light to a world file at `path`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        let file = File::create(path)
+            .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+        self.write_to(BufWriter::new(file))
+            .map_err(|e| format!("Failed to save {}: {}", path.display(), e))
+    }
+
+    /// Reads a world file into a fresh world seeded with `seed`.
+    pub fn load<P: AsRef<Path>>(
+        path: P,
+        elements: ElementRegistry,
+        seed: u64,
+    ) -> Result<World, String> {
+        let path = path.as_ref();
+        let file =
+            File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        World::read_from(BufReader::new(file), elements, seed)
+            .map_err(|e| format!("Failed to load {}: {}", path.display(), e))
+    }
+
+    pub fn write_to<W: Write>(&self, w: W) -> Result<(), String> {
+        let ambient = Ambient {
+            temperature: self.ambient_temperature,
+            light: self.ambient_light,
+        };
+        write_world(w, &self.grid, &self.elements, ambient)
+    }
+
+    pub fn read_from<R: Read>(r: R, elements: ElementRegistry, seed: u64) -> Result<World, String> {
+        let (grid, ambient) = read_world(r, &elements)?;
+        let mut world = World::new(grid, elements, seed);
+        world.ambient_temperature = ambient.temperature;
+        world.ambient_light = ambient.light;
+        Ok(world)
+    }
+}
+
+/// The world-wide settings stored next to the cells.
+struct Ambient {
+    temperature: f32,
+    light: f32,
+}
+
+impl Default for Ambient {
+    fn default() -> Self {
+        Ambient {
+            temperature: ROOM_TEMPERATURE,
+            light: 1.0,
+        }
+    }
+}
+
+fn write_world<W: Write>(
+    mut w: W,
+    grid: &Grid,
+    elements: &ElementRegistry,
+    ambient: Ambient,
+) -> Result<(), String> {
+    let io = |e: std::io::Error| e.to_string();
+    w.write_all(MAGIC).map_err(io)?;
+    w.write_all(&FORMAT_VERSION.to_le_bytes()).map_err(io)?;
+    w.write_all(&(grid.width() as u32).to_le_bytes())
+        .map_err(io)?;
+    w.write_all(&(grid.height() as u32).to_le_bytes())
+        .map_err(io)?;
+    w.write_all(&ambient.temperature.to_le_bytes())
+        .map_err(io)?;
+    w.write_all(&ambient.light.to_le_bytes()).map_err(io)?;
+    write_element_table(&mut w, elements)?;
+    let mut z = ZlibEncoder::new(w, Compression::default());
+    for p in grid.cells() {
+        z.write_all(&p.element.id().to_le_bytes()).map_err(io)?;
+        z.write_all(&p.age.to_le_bytes()).map_err(io)?;
+        z.write_all(&p.temperature.to_le_bytes()).map_err(io)?;
+        z.write_all(&p.burning.to_le_bytes()).map_err(io)?;
+        z.write_all(&[p.seed]).map_err(io)?;
+    }
+    z.finish().map_err(io)?.flush().map_err(io)
+}
+
+fn read_world<R: Read>(mut r: R, elements: &ElementRegistry) -> Result<(Grid, Ambient), String> {
+    let mut magic = [0; 4];
+    r.read_exact(&mut magic).map_err(|e| e.to_string())?;
+    if &magic != MAGIC {
+        return Err("not a world file".to_string());
+    }
+    let version = read_u16(&mut r)?;
+    if version != FORMAT_VERSION {
+        return Err(format!("unsupported world file version {}", version));
+    }
+    let width = read_u32(&mut r)?;
+    let height = read_u32(&mut r)?;
+    let cells = (width as u64)
+        .checked_mul(height as u64)
+        .filter(|&cells| width > 0 && height > 0 && cells <= MAX_WORLD_CELLS)
+        .ok_or_else(|| format!("bad world size {}x{}", width, height))?;
+    let ambient = Ambient {
+        temperature: f32::from_bits(read_u32(&mut r)?),
+        light: f32::from_bits(read_u32(&mut r)?),
+    };
+
+    let table = read_element_table(&mut r, elements)?;
+
+    // read every cell before allocating the grid, so a header claiming more cells than
+    // the file holds fails at the end of the data instead of allocating them all
+    let mut particles = Vec::new();
+    let mut z = ZlibDecoder::new(r);
+    for _ in 0..cells {
+        particles.push(Particle {
+            element: read_element(&mut z, &table)?,
+            age: read_u32(&mut z)?,
+            temperature: f32::from_bits(read_u32(&mut z)?),
+            burning: read_u32(&mut z)?,
+            seed: read_u8(&mut z)?,
+            ..Particle::default()
+        });
+    }
+    // read to the end of the compressed data, which checks it wasn't cut short
+    std::io::copy(&mut z, &mut std::io::sink()).map_err(|e| e.to_string())?;
+    let mut grid = Grid::with_size(width as i32, height as i32);
+    grid.cells_mut().copy_from_slice(&particles);
+    Ok((grid, ambient))
+}
+
+/// Writes the name of every element, so ids written after it can be read back by name.
+pub(crate) fn write_element_table<W: Write>(
+    w: &mut W,
+    elements: &ElementRegistry,
+) -> Result<(), String> {
+    let io = |e: std::io::Error| e.to_string();
+    w.write_all(&(elements.len() as u16).to_le_bytes())
+        .map_err(io)?;
+    for (_, def) in elements.iter() {
+        w.write_all(&(def.name.len() as u16).to_le_bytes())
+            .map_err(io)?;
+        w.write_all(def.name.as_bytes()).map_err(io)?;
+    }
+    Ok(())
+}
+
+/// Reads a table written by `write_element_table`, mapping the file's element ids onto
+/// this registry's.
+pub(crate) fn read_element_table<R: Read>(
+    r: &mut R,
+    elements: &ElementRegistry,
+) -> Result<Vec<Element>, String> {
+    let count = read_u16(r)?;
+    let mut table = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let len = read_u16(r)?;
+        let mut name = vec![0; len as usize];
+        r.read_exact(&mut name).map_err(|e| e.to_string())?;
+        let name = String::from_utf8(name).map_err(|e| e.to_string())?;
+        let element = elements
+            .by_name(&name)
+            .ok_or_else(|| format!("unknown element {}", name))?;
+        table.push(element);
+    }
+    Ok(table)
+}
+
+/// Reads an element id and looks it up in a table from `read_element_table`.
+pub(crate) fn read_element<R: Read>(r: &mut R, table: &[Element]) -> Result<Element, String> {
+    let id = read_u16(r)?;
+    table
+        .get(id as usize)
+        .copied()
+        .ok_or_else(|| format!("element id {} is not in the table", id))
+}
+
+pub(crate) fn read_u8<R: Read>(r: &mut R) -> Result<u8, String> {
+    let mut bytes = [0; 1];
+    r.read_exact(&mut bytes).map_err(|e| e.to_string())?;
+    Ok(bytes[0])
+}
+
+pub(crate) fn read_u16<R: Read>(r: &mut R) -> Result<u16, String> {
+    let mut bytes = [0; 2];
+    r.read_exact(&mut bytes).map_err(|e| e.to_string())?;
+    Ok(u16::from_le_bytes(bytes))
+}
+
+pub(crate) fn read_u32<R: Read>(r: &mut R) -> Result<u32, String> {
+    let mut bytes = [0; 4];
+    r.read_exact(&mut bytes).map_err(|e| e.to_string())?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+pub(crate) fn read_u64<R: Read>(r: &mut R) -> Result<u64, String> {
+    let mut bytes = [0; 8];
+    r.read_exact(&mut bytes).map_err(|e| e.to_string())?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::tests::{busy_world, cell_states, stepped};
+
+    /// The header and element table of a world file, up to the compressed cells.
+    fn header(version: u16, width: u32, height: u32, names: &[&str]) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(version.to_le_bytes());
+        bytes.extend(width.to_le_bytes());
+        bytes.extend(height.to_le_bytes());
+        bytes.extend((-5f32).to_le_bytes());
+        bytes.extend(0.25f32.to_le_bytes());
+        bytes.extend((names.len() as u16).to_le_bytes());
+        for name in names {
+            bytes.extend((name.len() as u16).to_le_bytes());
+            bytes.extend(name.as_bytes());
+        }
+        bytes
+    }
+
+    fn saved(world: &World) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        world.write_to(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn round_trip_keeps_cells_and_ambient() {
+        let mut world = stepped(busy_world(3), 50);
+        world.ambient_temperature = -5.0;
+        world.ambient_light = 0.25;
+        let loaded = World::read_from(&saved(&world)[..], world.elements.clone(), 3).unwrap();
+        assert_eq!(loaded.grid.width(), world.grid.width());
+        assert_eq!(loaded.grid.height(), world.grid.height());
+        assert!(cell_states(&loaded.grid) == cell_states(&world.grid));
+        assert_eq!(loaded.ambient_temperature, -5.0);
+        assert_eq!(loaded.ambient_light, 0.25);
+    }
+
+    #[test]
+    fn hand_built_file_loads_by_element_name() {
+        let elements = ElementRegistry::builtin();
+        let sand = elements.by_name("Sand").unwrap();
+        // the ids in the file follow its own table, where Sand is 0
+        let mut bytes = header(1, 2, 1, &["Sand", "Air"]);
+        let mut z = ZlibEncoder::new(Vec::new(), Compression::default());
+        for (id, seed) in [(0u16, 9u8), (1, 200)] {
+            z.write_all(&id.to_le_bytes()).unwrap();
+            z.write_all(&7u32.to_le_bytes()).unwrap();
+            z.write_all(&30f32.to_le_bytes()).unwrap();
+            z.write_all(&0u32.to_le_bytes()).unwrap();
+            z.write_all(&[seed]).unwrap();
+        }
+        bytes.extend(z.finish().unwrap());
+
+        let world = World::read_from(&bytes[..], elements, 0).unwrap();
+        let (first, second) = (world.grid.get(0, 0).unwrap(), world.grid.get(1, 0).unwrap());
+        assert_eq!(first.element, sand);
+        assert_eq!(second.element, Element::AIR);
+        assert_eq!((first.age, first.temperature, first.seed), (7, 30.0, 9));
+        assert_eq!(second.seed, 200);
+        assert_eq!(world.ambient_temperature, -5.0);
+        assert_eq!(world.ambient_light, 0.25);
+    }
+
+    #[test]
+    fn other_versions_are_refused() {
+        let elements = ElementRegistry::builtin();
+        for version in [0, 2] {
+            let bytes = header(version, 1, 1, &[]);
+            let error = Grid::read_from(&bytes[..], &elements).err();
+            let expected = format!("unsupported world file version {}", version);
+            assert_eq!(error, Some(expected));
+        }
+    }
+
+    #[test]
+    fn truncated_files_are_refused() {
+        let world = busy_world(0);
+        let bytes = saved(&world);
+        for len in [3, 20, bytes.len() / 2, bytes.len() - 1] {
+            let result = World::read_from(&bytes[..len], world.elements.clone(), 0);
+            assert!(result.is_err(), "{} of {} bytes loaded", len, bytes.len());
+        }
+    }
+
+    #[test]
+    fn oversized_worlds_are_refused_before_reading_cells() {
+        let elements = ElementRegistry::builtin();
+        let bytes = header(1, 1 << 13, 1 << 13, &[]);
+        let error = Grid::read_from(&bytes[..], &elements).err();
+        assert_eq!(error.as_deref(), Some("bad world size 8192x8192"));
+        let bytes = header(1, 0, 16, &[]);
+        assert!(Grid::read_from(&bytes[..], &elements).is_err());
+    }
+}
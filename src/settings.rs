@@ -1,3 +1,46 @@
+// initial window size; the window can be resized, and F11 makes it fullscreen
 pub const WINDOW_WIDTH: u32 = 1200;
 pub const WINDOW_HEIGHT: u32 = 900;
+#[allow(dead_code)]
 pub const MAX_PARTICLES: usize = 8192;
+
+// default dimensions of the simulated world, in cells; --width and --height override them
+pub const GRID_WIDTH: i32 = 64;
+pub const GRID_HEIGHT: i32 = 64;
+// largest width or height accepted on the command line
+pub const MAX_GRID_SIZE: i64 = fallingsand::save::MAX_WORLD_SIDE as i64;
+
+// world file written by F5 and read back by F9
+pub const QUICKSAVE_PATH: &str = "quicksave.bin";
+
+// F6 writes the world here as a PNG, each cell SCREENSHOT_SCALE pixels across
+pub const SCREENSHOT_PATH: &str = "screenshot.png";
+pub const SCREENSHOT_SCALE: u32 = 4;
+
+// F2 writes the session so far here, for `fallingsand-rs replay`
+pub const REPLAY_PATH: &str = "replay.bin";
+
+// brush radius limit for Ctrl + the scroll wheel and [ ], and the share of cells spray
+// mode (V) fills
+pub const MAX_BRUSH_RADIUS: i32 = 16;
+pub const SPRAY_DENSITY: f32 = 0.1;
+
+// simulation ticks per second at normal speed, and the render frame rate cap. the
+// simulation catches up by at most MAX_TICKS_PER_FRAME ticks per frame, so a slow frame
+// doesn't snowball
+pub const TICK_RATE: f64 = 144.0;
+pub const FRAME_RATE: f64 = 144.0;
+pub const MAX_TICKS_PER_FRAME: u32 = 8;
+// speeds picked with - and =, as multiples of TICK_RATE
+pub const SPEEDS: [f64; 7] = [0.1, 0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+pub const NORMAL_SPEED: usize = 3;
+
+// glow around emissive elements, toggled with G: how far it spreads in cells, and how
+// bright it is
+pub const BLOOM_RADIUS: usize = 3;
+pub const BLOOM_STRENGTH: f32 = 4.0;
+
+// ambient light levels cycled with L, from fully lit to pitch dark, and the fraction of
+// light from emissive elements kept at each cell it spreads
+pub const AMBIENT_LIGHT_LEVELS: [f32; 4] = [1.0, 0.5, 0.2, 0.0];
+pub const LIGHT_FALLOFF: f32 = 0.85;
@@ -0,0 +1,88 @@
+/*
+    How particles are coloured on screen. Each particle carries a seed from when it was
+    placed, which picks one of its element's shades and nudges its brightness by up to the
+    element's noise, so a pile of sand is grainy rather than one flat colour. Shading
+    effects are layered on top: flames flicker and fade as they age, lava pulses and
+    glows brighter the hotter it is, and anything on fire flickers between its own colour
+    and the colour of embers. Burning particles give off light like emissive ones do.
+
+    The flat `ElementDef::color` is still what palettes and PNG files use, so images
+    convert back to the same elements.
+*/
+
+use crate::{
+    color::Color,
+    element::{ElementDef, Shading},
+    particle::{color_seed, Particle, ROOM_TEMPERATURE},
+    world::World,
+};
+
+/// What glowing particles brighten towards.
+const GLOW_COLOR: Color = Color::rgb(255, 255, 160);
+
+/// What burning particles are tinted towards.
+const EMBER_COLOR: Color = Color::rgb(255, 110, 20);
+
+impl ElementDef {
+    /// Whether `p`, a particle of this element, lights up its surroundings: emissive
+    /// elements always do, and anything else while it burns.
+    pub fn gives_light(&self, p: &Particle) -> bool {
+        self.emissive || p.burning > 0
+    }
+
+    /// The colour to draw `p`, a particle of this element, on tick `frame_clock`.
+    pub fn shade(&self, p: &Particle, frame_clock: u32) -> Color {
+        let seed = match self.shading {
+            // a fresh pick every tick of the particle's life
+            Shading::Flicker => color_seed(p.seed as i32, 0, p.age),
+            _ => p.seed,
+        };
+        let base = match self.shades.len() {
+            0 => self.color,
+            n => self.shades[seed as usize % n],
+        };
+        // a different part of the seed than picked the shade, so the two don't line up
+        let offset = ((seed.rotate_left(4) as i32) * 2 - 255) * self.noise as i32 / 255;
+        let color = base.brightened(offset);
+        let color = if p.burning > 0 {
+            let flicker = color_seed(p.seed as i32, 1, p.age) as f32 / 255.0;
+            color.mix(EMBER_COLOR, 0.5 + 0.4 * flicker)
+        } else {
+            color
+        };
+
+        match self.shading {
+            Shading::None => color,
+            Shading::Flicker if self.lifetime > 0 => {
+                let age = p.age.min(self.lifetime) as f32 / self.lifetime as f32;
+                color.scaled(1.0 - 0.6 * age)
+            }
+            Shading::Flicker => color,
+            Shading::Glow => {
+                let heat = if self.temperature > ROOM_TEMPERATURE {
+                    ((p.temperature - ROOM_TEMPERATURE) / (self.temperature - ROOM_TEMPERATURE))
+                        .clamp(0.0, 1.0)
+                } else {
+                    1.0
+                };
+                let phase = (frame_clock as f32 + seed as f32 * 4.0) * 0.05;
+                let pulse = 0.5 + 0.5 * phase.sin();
+                color
+                    .scaled(0.6 + 0.4 * heat)
+                    .mix(GLOW_COLOR, 0.35 * heat * pulse)
+            }
+        }
+    }
+}
+
+impl World {
+    /// Fills `buf` with the grid as row-major RGBA bytes, one shaded pixel per cell.
+    pub fn fill_rgba(&self, buf: &mut Vec<u8>) {
+        buf.clear();
+        buf.reserve(self.grid.cells().len() * 4);
+        for p in self.grid.cells() {
+            let c = self.elements.get(p.element).shade(p, self.frame_clock);
+            buf.extend_from_slice(&[c.r, c.g, c.b, c.a]);
+        }
+    }
+}
@@ -0,0 +1,291 @@
+/*
+    Geometry for painting: each tool turns its input into the list of cells it covers, and
+    `World::paint` does the painting, so every tool respects the brush mode and density
+    and can be recorded. The functions only need a `Grid` (flood fill) or nothing at all,
+    so they work just as well from scripts and tests.
+
+    `Shape::cells` only walks the part of a shape inside the grid, and `line_within` only
+    the part of a line inside the given bounds, so a huge rectangle, circle or stroke from
+    a damaged replay costs no more than the grid itself.
+*/
+
+use crate::{element::Element, grid::Grid};
+
+/// A shape drawn with one of the geometry tools.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shape {
+    /// The rectangle with opposite corners `from` and `to`.
+    Rect {
+        from: (i32, i32),
+        to: (i32, i32),
+        filled: bool,
+    },
+    Circle {
+        center: (i32, i32),
+        radius: i32,
+        filled: bool,
+    },
+    /// The connected region of one element that contains (x, y).
+    Fill { x: i32, y: i32 },
+}
+
+impl Shape {
+    /// The cells the shape covers in `grid`, leaving out any outside it.
+    pub fn cells(&self, grid: &Grid) -> Vec<(i32, i32)> {
+        let bounds = (0, 0, grid.width() - 1, grid.height() - 1);
+        match *self {
+            Shape::Rect { from, to, filled } => rect_within(from, to, filled, bounds),
+            Shape::Circle {
+                center,
+                radius,
+                filled,
+            } => circle_within(center, radius, filled, bounds),
+            Shape::Fill { x, y } => flood_fill(grid, x, y),
+        }
+    }
+}
+
+/// The cells on the line from `from` to `to`, both ends included (Bresenham).
+pub fn line(from: (i32, i32), to: (i32, i32)) -> Vec<(i32, i32)> {
+    // in i64 so the distances can't overflow, whatever the end points
+    let (mut x, mut y) = (from.0 as i64, from.1 as i64);
+    let to = (to.0 as i64, to.1 as i64);
+    let dx = (to.0 - x).abs();
+    let dy = -(to.1 - y).abs();
+    let sx = if x < to.0 { 1 } else { -1 };
+    let sy = if y < to.1 { 1 } else { -1 };
+    let mut err = dx + dy;
+    let mut points = Vec::with_capacity((dx - dy + 1) as usize);
+    loop {
+        points.push((x as i32, y as i32));
+        if (x, y) == to {
+            return points;
+        }
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            y += sy;
+        }
+    }
+}
+
+/// The cells of the line from `from` to `to` that lie within `bounds`. The line is cut
+/// where it crosses the bounds before it is walked, so the cells may be off by one from
+/// `line`'s near where it enters and leaves.
+pub fn line_within(from: (i32, i32), to: (i32, i32), bounds: Bounds) -> Vec<(i32, i32)> {
+    // Liang-Barsky: narrow the part of the line kept, t0..t1, against each side in turn
+    let (x, y) = (from.0 as f64, from.1 as f64);
+    let (dx, dy) = (to.0 as f64 - x, to.1 as f64 - y);
+    let (mut t0, mut t1) = (0.0, 1.0);
+    let sides = [
+        (-dx, x - bounds.0 as f64),
+        (dx, bounds.2 as f64 - x),
+        (-dy, y - bounds.1 as f64),
+        (dy, bounds.3 as f64 - y),
+    ];
+    for (p, q) in sides {
+        if p == 0.0 {
+            if q < 0.0 {
+                return Vec::new();
+            }
+        } else if p < 0.0 {
+            t0 = f64::max(t0, q / p);
+        } else {
+            t1 = f64::min(t1, q / p);
+        }
+    }
+    if t0 > t1 {
+        return Vec::new();
+    }
+    let at = |t: f64| ((x + t * dx).round() as i32, (y + t * dy).round() as i32);
+    line(at(t0), at(t1))
+}
+
+/// Cells from (x0, y0) to (x1, y1) inclusive, the area a shape is clipped to.
+pub type Bounds = (i32, i32, i32, i32);
+
+const UNBOUNDED: Bounds = (i32::MIN, i32::MIN, i32::MAX, i32::MAX);
+
+/// The cells of the rectangle with opposite corners `a` and `b`, or just its border.
+pub fn rect(a: (i32, i32), b: (i32, i32), filled: bool) -> Vec<(i32, i32)> {
+    rect_within(a, b, filled, UNBOUNDED)
+}
+
+fn rect_within(a: (i32, i32), b: (i32, i32), filled: bool, bounds: Bounds) -> Vec<(i32, i32)> {
+    let (x0, x1) = (a.0.min(b.0), a.0.max(b.0));
+    let (y0, y1) = (a.1.min(b.1), a.1.max(b.1));
+    // the border is where the whole rectangle's is, not the clipped one's
+    let xs = x0.max(bounds.0)..=x1.min(bounds.2);
+    (y0.max(bounds.1)..=y1.min(bounds.3))
+        .flat_map(|y| xs.clone().map(move |x| (x, y)))
+        .filter(|&(x, y)| filled || x == x0 || x == x1 || y == y0 || y == y1)
+        .collect()
+}
+
+/// The cells of the disc around `center`, or just its edge. Uses the same rule as a
+/// circular brush, so the two line up.
+pub fn circle(center: (i32, i32), radius: i32, filled: bool) -> Vec<(i32, i32)> {
+    circle_within(center, radius, filled, UNBOUNDED)
+}
+
+fn circle_within(center: (i32, i32), radius: i32, filled: bool, bounds: Bounds) -> Vec<(i32, i32)> {
+    // in i64, and capped well below where the squares would overflow
+    let r = radius.clamp(0, 1 << 30) as i64;
+    let (cx, cy) = (center.0 as i64, center.1 as i64);
+    let inside = move |dx: i64, dy: i64| dx * dx + dy * dy <= r * r;
+    let dxs = (-r).max(bounds.0 as i64 - cx)..=r.min(bounds.2 as i64 - cx);
+    ((-r).max(bounds.1 as i64 - cy)..=r.min(bounds.3 as i64 - cy))
+        .flat_map(|dy| dxs.clone().map(move |dx| (dx, dy)))
+        .filter(|&(dx, dy)| inside(dx, dy))
+        .filter(|&(dx, dy)| {
+            filled
+                || !(inside(dx - 1, dy) && inside(dx + 1, dy))
+                || !(inside(dx, dy - 1) && inside(dx, dy + 1))
+        })
+        .map(|(dx, dy)| ((cx + dx) as i32, (cy + dy) as i32))
+        .collect()
+}
+
+/// The cells connected to (x, y) through direct neighbours holding the same element,
+/// nearest first. Empty if (x, y) is outside the grid.
+pub fn flood_fill(grid: &Grid, x: i32, y: i32) -> Vec<(i32, i32)> {
+    let target: Element = match grid.get(x, y) {
+        Some(p) => p.element,
+        None => return Vec::new(),
+    };
+    let mut seen = vec![false; grid.cells().len()];
+    seen[grid.index(x, y)] = true;
+    let mut region = vec![(x, y)];
+    let mut next = 0;
+    while next < region.len() {
+        let (x, y) = region[next];
+        next += 1;
+        for (dx, dy) in [(0, -1), (-1, 0), (1, 0), (0, 1)] {
+            let (nx, ny) = (x + dx, y + dy);
+            match grid.get(nx, ny) {
+                Some(p) if p.element == target => {}
+                _ => continue,
+            }
+            let i = grid.index(nx, ny);
+            if !seen[i] {
+                seen[i] = true;
+                region.push((nx, ny));
+            }
+        }
+    }
+    region
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::particle::Particle;
+
+    /// 16 by 16 cells.
+    const GRID: Bounds = (0, 0, 15, 15);
+
+    fn inside((x, y): (i32, i32)) -> bool {
+        (0..16).contains(&x) && (0..16).contains(&y)
+    }
+
+    #[test]
+    fn lines_inside_the_bounds_are_not_changed() {
+        assert_eq!(line_within((1, 2), (14, 9), GRID), line((1, 2), (14, 9)));
+        assert_eq!(line_within((3, 3), (3, 3), GRID), vec![(3, 3)]);
+    }
+
+    #[test]
+    fn lines_are_cut_to_the_bounds() {
+        let far = 1 << 16;
+        let cells = line_within((-far, -far), (15 + far, 15 + far), GRID);
+        assert_eq!(cells, (0..16).map(|i| (i, i)).collect::<Vec<_>>());
+        let cells = line_within((-far, 4), (far, 4), GRID);
+        assert_eq!(cells, (0..16).map(|x| (x, 4)).collect::<Vec<_>>());
+        assert!(line_within((-far, -far), (-1, far), GRID).is_empty());
+        assert!(line_within((20, 20), (20, 20), GRID).is_empty());
+    }
+
+    #[test]
+    fn huge_shapes_are_cut_to_the_grid() {
+        let grid = Grid::with_size(16, 16);
+        let far = 1 << 16;
+        let rect = Shape::Rect {
+            from: (-far, -far),
+            to: (far, far),
+            filled: true,
+        };
+        let cells = rect.cells(&grid);
+        assert_eq!(cells.len(), 256);
+        assert!(cells.into_iter().all(inside));
+        // the border is the whole rectangle's, all of it outside the grid
+        let border = Shape::Rect {
+            from: (-far, -far),
+            to: (far, far),
+            filled: false,
+        };
+        assert!(border.cells(&grid).is_empty());
+        let circle = Shape::Circle {
+            center: (8, 8),
+            radius: i32::MAX,
+            filled: true,
+        };
+        assert_eq!(circle.cells(&grid).len(), 256);
+        let edge = Shape::Circle {
+            center: (8, 8),
+            radius: 5,
+            filled: false,
+        };
+        assert_eq!(
+            edge.cells(&grid),
+            circle_within((8, 8), 5, false, UNBOUNDED)
+        );
+    }
+
+    /// A 16 by 16 grid of Air with some other element in the listed cells.
+    fn walled(cells: &[(i32, i32)]) -> Grid {
+        let mut grid = Grid::with_size(16, 16);
+        let wall = Particle {
+            element: Element::from_id(1),
+            ..Particle::default()
+        };
+        for &(x, y) in cells {
+            grid.set(x, y, wall);
+        }
+        grid
+    }
+
+    #[test]
+    fn flood_fill_spreads_through_direct_neighbours_only() {
+        // a diagonal line splits the grid in two, since fill doesn't go corner to corner
+        let diagonal: Vec<_> = (0..16).map(|i| (i, 15 - i)).collect();
+        let grid = walled(&diagonal);
+        let region = flood_fill(&grid, 0, 0);
+        assert_eq!(region.len(), 120);
+        assert_eq!(region[0], (0, 0));
+        assert!(region.iter().all(|&(x, y)| x + y < 15));
+    }
+
+    #[test]
+    fn flood_fill_stops_at_other_elements() {
+        let mut ring = rect((4, 4), (8, 8), false);
+        let grid = walled(&ring);
+        let inside = flood_fill(&grid, 6, 6);
+        assert_eq!(inside.len(), 9);
+        // filling a wall cell takes the whole ring and nothing else
+        let mut walls = flood_fill(&grid, 4, 4);
+        walls.sort();
+        ring.sort();
+        assert_eq!(walls, ring);
+    }
+
+    #[test]
+    fn flood_fill_outside_the_grid_is_empty() {
+        let grid = walled(&[]);
+        assert!(flood_fill(&grid, -1, 3).is_empty());
+        assert!(flood_fill(&grid, 3, 16).is_empty());
+    }
+}
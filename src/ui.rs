@@ -1,4 +1,3 @@
-use enum_iterator::all;
 use sdl2::{
     pixels::Color,
     rect::Rect,
@@ -8,18 +7,21 @@ use sdl2::{
 
 use sdl2::video::Window;
 
-use crate::{
-    element::Element,
+use fallingsand::{
+    brush::{Brush, BrushMode, BrushShape},
+    element::{Element, ElementRegistry},
     particle::Particle,
-    settings::{MAX_PARTICLES, WINDOW_WIDTH},
 };
 
+use crate::{render::sdl_color, settings::MAX_PARTICLES};
+
 // draw_particle_count
 // should draw the total number of particles in the top right
+#[allow(dead_code)]
 pub fn draw_particle_count(
     canvas: &mut Canvas<Window>,
     font: &Font,
-    particles: &Vec<Particle>,
+    particles: &[Particle],
     texture_creator: &TextureCreator<sdl2::video::WindowContext>,
 ) {
     let text = format!("Particles: {} / {}", particles.len(), MAX_PARTICLES);
@@ -33,7 +35,9 @@ pub fn draw_particle_count(
         .map_err(|e| e.to_string())
         .unwrap();
     let TextureQuery { width, height, .. } = texture.query();
-    let dst = Rect::new(WINDOW_WIDTH as i32 - width as i32 - 10, 10, width, height);
+    // the window may have been resized
+    let (window_width, _) = canvas.output_size().unwrap();
+    let dst = Rect::new(window_width as i32 - width as i32 - 10, 10, width, height);
     canvas.copy(&texture, None, dst).unwrap();
 }
 
@@ -41,23 +45,23 @@ pub fn draw_particle_options(
     canvas: &mut Canvas<Window>,
     small_font: &Font,
     large_font: &Font,
+    elements: &ElementRegistry,
     current_element: &Element,
     texture_creator: &TextureCreator<sdl2::video::WindowContext>,
 ) {
     let mut y = 10;
-    let elements: Vec<Element> = all::<Element>().collect::<Vec<_>>();
-    for element in elements {
+    for (element, def) in elements.iter() {
         // skip air as an option
-        let text = format!("{}", element);
+        let text = def.name.to_string();
         let font = if element == *current_element {
             large_font
         } else {
             small_font
         };
-        let color = if element == Element::Air {
+        let color = if element == Element::AIR {
             Color::RGBA(255, 255, 255, 255)
         } else {
-            element.color()
+            sdl_color(def.color)
         };
 
         let surface = font
@@ -75,3 +79,77 @@ pub fn draw_particle_options(
         y += 40;
     }
 }
+
+// draw_brush_status
+// shows the tool, brush shape, size and mode in the top right
+pub fn draw_brush_status(
+    canvas: &mut Canvas<Window>,
+    font: &Font,
+    tool: &str,
+    filled: bool,
+    brush: &Brush,
+    elements: &ElementRegistry,
+    texture_creator: &TextureCreator<sdl2::video::WindowContext>,
+) {
+    let shape = match brush.shape {
+        BrushShape::Circle => "circle",
+        BrushShape::Square => "square",
+    };
+    let mut text = format!("{}: {} {}", tool, shape, brush.radius);
+    if matches!(tool, "rect" | "circle") && !filled {
+        text.push_str(", outline");
+    }
+    if brush.density < 1.0 {
+        text.push_str(", spray");
+    }
+    match brush.mode {
+        BrushMode::Overwrite => {}
+        BrushMode::IntoAir => text.push_str(", into air"),
+        BrushMode::Replace(target) => {
+            text.push_str(&format!(", replace {}", elements.get(target).name))
+        }
+    }
+
+    draw_text_right(canvas, font, &text, 10, texture_creator);
+}
+
+// draw_sim_status
+// shows whether the simulation is paused and how fast it runs, under the brush status
+pub fn draw_sim_status(
+    canvas: &mut Canvas<Window>,
+    font: &Font,
+    paused: bool,
+    speed: f64,
+    texture_creator: &TextureCreator<sdl2::video::WindowContext>,
+) {
+    let text = if paused {
+        "Paused (space resumes, . steps)".to_string()
+    } else {
+        format!("Speed: {}x", speed)
+    };
+    draw_text_right(canvas, font, &text, 40, texture_creator);
+}
+
+// draws a line of white text against the right edge of the window
+fn draw_text_right(
+    canvas: &mut Canvas<Window>,
+    font: &Font,
+    text: &str,
+    y: i32,
+    texture_creator: &TextureCreator<sdl2::video::WindowContext>,
+) {
+    let surface = font
+        .render(text)
+        .blended(Color::RGBA(255, 255, 255, 255))
+        .map_err(|e| e.to_string())
+        .unwrap();
+    let texture = surface
+        .as_texture(texture_creator)
+        .map_err(|e| e.to_string())
+        .unwrap();
+    let TextureQuery { width, height, .. } = texture.query();
+    // the window may have been resized
+    let (window_width, _) = canvas.output_size().unwrap();
+    let dst = Rect::new(window_width as i32 - width as i32 - 10, y, width, height);
+    canvas.copy(&texture, None, dst).unwrap();
+}
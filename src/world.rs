@@ -0,0 +1,194 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+use crate::{
+    element::ElementRegistry, grid::Grid, heat::conduct_heat, history::History,
+    particle::ROOM_TEMPERATURE, particle_behaviour::step_particles,
+};
+
+/// The random number generator that drives the simulation.
+/// ChaCha8 produces the same stream on every platform, which `StdRng` does not promise.
+pub type SimRng = ChaCha8Rng;
+
+/// A grid together with the state needed to step it deterministically.
+/// Two worlds built from the same grid, elements and seed stay identical for as long as
+/// they receive the same edits.
+#[derive(Clone)]
+pub struct World {
+    pub grid: Grid,
+    pub elements: ElementRegistry,
+    pub rng: SimRng,
+    pub seed: u64,
+    /// The current tick, stamped on particles as they move. Never 0, which is the stamp
+    /// of particles loaded from a file, so those get to move on the first tick.
+    pub frame_clock: u32,
+    /// The temperature that cooling elements, like Air, relax towards.
+    pub ambient_temperature: f32,
+    /// How bright cells are with no light source nearby, from 0 for pitch dark to 1 for
+    /// fully lit. Only affects drawing.
+    pub ambient_light: f32,
+    /// Edits made through `apply`, for undo and redo.
+    pub history: History,
+}
+
+impl World {
+    pub fn new(grid: Grid, elements: ElementRegistry, seed: u64) -> World {
+        World {
+            grid,
+            elements,
+            rng: SimRng::seed_from_u64(seed),
+            seed,
+            frame_clock: 1,
+            ambient_temperature: ROOM_TEMPERATURE,
+            ambient_light: 1.0,
+            history: History::default(),
+        }
+    }
+
+    /// Advances the simulation by one tick.
+    pub fn step(&mut self) {
+        self.grid.begin_step();
+        conduct_heat(&mut self.grid, &self.elements, self.ambient_temperature);
+        step_particles(
+            &mut self.grid,
+            &self.elements,
+            &mut self.rng,
+            self.frame_clock,
+        );
+        self.frame_clock = self.frame_clock.wrapping_add(1).max(1);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::{action::Action, brush::Brush, element::Element, tools::Shape};
+
+    /// A world with falling, flowing and burning elements in it, big enough to span
+    /// several chunks.
+    pub(crate) fn busy_world(seed: u64) -> World {
+        let elements = ElementRegistry::builtin();
+        let element = |name| elements.by_name(name).unwrap();
+        let shapes = [
+            (
+                Shape::Rect {
+                    from: (0, 60),
+                    to: (95, 63),
+                    filled: true,
+                },
+                "Wall",
+            ),
+            (
+                Shape::Rect {
+                    from: (10, 30),
+                    to: (40, 45),
+                    filled: true,
+                },
+                "Water",
+            ),
+            (
+                Shape::Circle {
+                    center: (60, 15),
+                    radius: 10,
+                    filled: true,
+                },
+                "Sand",
+            ),
+            (
+                Shape::Rect {
+                    from: (70, 40),
+                    to: (90, 59),
+                    filled: true,
+                },
+                "Wood",
+            ),
+            (
+                Shape::Circle {
+                    center: (80, 30),
+                    radius: 4,
+                    filled: true,
+                },
+                "Lava",
+            ),
+            (
+                Shape::Circle {
+                    center: (25, 10),
+                    radius: 6,
+                    filled: false,
+                },
+                "Fire",
+            ),
+        ];
+        let mut world = World::new(Grid::with_size(96, 64), elements.clone(), seed);
+        for (shape, name) in shapes {
+            world.apply(&Action::Shape {
+                shape,
+                element: element(name),
+                brush: Brush::default(),
+            });
+        }
+        world
+    }
+
+    /// Everything about each cell that is saved and simulated, with temperatures as bits
+    /// so that they compare exactly.
+    pub(crate) fn cell_states(grid: &Grid) -> Vec<(Element, u32, u32, u32, u8)> {
+        grid.cells()
+            .iter()
+            .map(|p| (p.element, p.age, p.temperature.to_bits(), p.burning, p.seed))
+            .collect()
+    }
+
+    /// A 16 by 16 world of `elements` with the named particles placed in otherwise empty
+    /// space.
+    pub(crate) fn world_with(elements: ElementRegistry, cells: &[(i32, i32, &str)]) -> World {
+        let mut grid = Grid::with_size(16, 16);
+        for &(x, y, name) in cells {
+            let element = elements.by_name(name).unwrap();
+            grid.set(x, y, elements.spawn(element, 0, x, y));
+        }
+        World::new(grid, elements, 0)
+    }
+
+    /// The name of the element at (x, y).
+    pub(crate) fn name_at(world: &World, x: i32, y: i32) -> &str {
+        let element = world.grid.get(x, y).unwrap().element;
+        &world.elements.get(element).name
+    }
+
+    pub(crate) fn stepped(mut world: World, ticks: u32) -> World {
+        for _ in 0..ticks {
+            world.step();
+        }
+        world
+    }
+
+    #[test]
+    fn same_seed_gives_same_grid() {
+        let a = stepped(busy_world(42), 150);
+        let b = stepped(busy_world(42), 150);
+        assert!(cell_states(&a.grid) == cell_states(&b.grid));
+        assert!(a.rng == b.rng);
+    }
+
+    #[test]
+    fn different_seeds_give_different_grids() {
+        let a = stepped(busy_world(1), 100);
+        let b = stepped(busy_world(2), 100);
+        assert!(cell_states(&a.grid) != cell_states(&b.grid));
+    }
+
+    #[test]
+    fn thread_count_does_not_change_the_grid() {
+        let in_pool = |threads| {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            pool.install(|| stepped(busy_world(7), 100))
+        };
+        let (one, four) = (in_pool(1), in_pool(4));
+        assert!(cell_states(&one.grid) == cell_states(&four.grid));
+        assert!(one.rng == four.rng);
+    }
+}